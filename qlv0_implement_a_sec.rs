@@ -9,141 +9,7352 @@
 * The service uses the following components:
 * 
 * 1. **API Gateway**: Handles incoming requests and routes them to the 
-*    appropriate service.
+*    appropriate service. Served over HTTP or rustls-terminated HTTPS; with a
+*    client CA bundle configured, verified client certificates whose SAN or
+*    subject CN is listed in Config authenticate in place of an API key.
 * 
 * 2. **Notifier Service**: Responsible for sending notifications to users.
+*    Each event type is routed to one or more NotificationChannels, and the
+*    response reports the delivery outcome per channel. Channels that render
+*    content never see events sealed to a recipient, and get others with
+*    their policy-encrypted fields removed. The webhook channel
+*    POSTs the signed, encrypted event to each endpoint a tenant registered
+*    at /api/webhooks, with an HMAC-SHA256 signature per endpoint. Endpoints
+*    must be https URLs whose host resolves only to public addresses; this is
+*    checked at registration and again, pinning the addresses, on delivery.
+*    Config may lift this for development against a local listener.
+*    Failed deliveries are retried with backoff within a per-endpoint deadline. The email
+*    channel renders per-event-type subject, text and HTML templates and
+*    sends them through an SMTP relay (STARTTLS, implicit TLS or plain for a
+*    local sink). Chat channels post Slack Block Kit, Teams MessageCard or
+*    Adaptive Card, or Discord embed payloads to incoming webhooks, formatted
+*    per channel and event type, and wait out 429s as asked by Retry-After.
+*    The SMS channel drives any HTTP SMS provider through a configurable
+*    request template, validates E.164 numbers and splits long messages
+*    into numbered segments.
 * 
-* 3. **Encryption Service**: Encrypts sensitive information before sending 
-*    notifications.
+* 3. **Encryption Service**: Encrypts sensitive information with AES-256-GCM
+*    before sending notifications, and decrypts it for downstream consumers.
+*    Each event gets its own data key, wrapped by a key-encryption key held
+*    by a pluggable KeyProvider (envelope encryption). Per-event-type
+*    policies name the JSON paths to encrypt, leaving routing fields in clear.
+*    Event types without one get a configurable default, by default the whole
+*    payload, so channels can render only their id, type and recipient.
 *
-* 4. **API Key Authenticator**: Verifies the authenticity of API keys.
+* 4. **API Key Authenticator**: Verifies the authenticity of API keys. Keys
+*    are issued as `prefix.secret`; only the prefix and a SHA-256 hash of the
+*    secret are stored, and secrets are compared in constant time. Keys live
+*    in a KeyStore: Postgres, SQLite or in-memory, selected via Config.
+*    With SQLite or in-memory keys the service also runs without Postgres:
+*    events are delivered but not stored, and recipient key and webhook
+*    endpoints answer 503 `storage_disabled`.
+*    Each key carries scopes (e.g. `notify:send`, `notify:send:<event type>`)
+*    that the gateway enforces before routing, answering 403 when one is missing.
+*    Expired and revoked keys are rejected with distinct error codes, and
+*    last-used timestamps are written in the background. Verified keys are
+*    cached briefly and dropped when a key is revoked.
+*    Repeated failures from one IP are answered with growing delays, then a
+*    temporary ban and a security event. Failures against one key prefix,
+*    from any IP, are delayed and reported too, but never ban the key. Keys are managed through /admin/keys, which requires the
+*    `keys:admin` scope.
+*    Instead of sending API-KEY, callers may sign each request with
+*    HMAC-SHA256; signed requests must be fresh and their nonces are never
+*    accepted twice.
+*    Callers holding OIDC-issued JWTs may send `Authorization: Bearer`;
+*    tokens are verified against a JWKS file or URL, and their claims supply
+*    the caller's scopes and tenant. /api/notify is throttled per caller by
+*    a token bucket plus daily and monthly quotas, set per key or defaulted
+*    from Config, and optionally per source IP; throttled requests get 429.
+*
+* 5. **Key Rotation**: Activates a new key-encryption key, keeps older keys
+*    decrypt-only, and re-wraps stored data keys in a resumable background job.
+*
+* 6. **Recipient Key Registry**: Recipients register X25519 public keys; their
+*    data keys are sealed to that key so the service can't read the content.
+*
+* 7. **Notification Signer**: Signs every delivered payload with Ed25519 and
+*    publishes the public keys as a JWKS at /.well-known/jwks.json.
+*
+* 8. **Audit Log**: Records authentication outcomes, key management and every
+*    notification with principal, request id (X-Request-Id), event id and
+*    outcome, in Postgres and optionally a JSON-lines file. Records form a
+*    SHA-256 hash chain, checked by `verify-audit-log`. Records are queued
+*    and appended in batches; any dropped or not written are counted in /health.
+*
+* 9. **Tenants**: Every API key belongs to a tenant, and callers only see
+*    their tenant's events and recipient keys. Tenants may have their own
+*    key-encryption key file, default rate limits and channel credentials.
 *
 * This implementation uses Rust's async/await pattern to handle 
 * asynchronous operations.
 *
 * Dependencies:
 * 
-* actix-web = "3"
+* actix-web = { version = "4", features = ["rustls"] }
+* actix-tls = { version = "3", features = ["accept", "rustls"] }
+* rustls = "0.20"
+* rustls-pemfile = "1"
+* x509-parser = "0.15"
 * serde = { version = "1.0", features = ["derive"] }
+* serde_json = "1.0"
 * tokio = { version = "1", features = ["full"] }
-* sqlx = { version = "0.5", features = ["postgres"] }
-* argon2 = "0.3"
-* uuid = { version = "0.8", features = ["v4"] }
+* sqlx = { version = "0.5", features = ["runtime-tokio-rustls", "postgres", "sqlite", "uuid", "json", "chrono"] }
+* async-trait = "0.1"
+* aes-gcm = "0.9"
+* rand = "0.8"
+* base64 = "0.13"
+* uuid = { version = "0.8", features = ["v4", "serde"] }
+* x25519-dalek = { version = "2", features = ["static_secrets"] }
+* ed25519-dalek = "2"
+* hkdf = "0.12"
+* hmac = "0.12"
+* sha2 = "0.10"
+* subtle = "2"
+* hex = "0.4"
+* chrono = { version = "0.4", features = ["serde"] }
+* jsonwebtoken = "8"
+* reqwest = { version = "0.11.14", features = ["json"] }
+* futures = "0.3"
+* lettre = { version = "0.10", default-features = false, features = ["builder", "pool", "smtp-transport", "tokio1-rustls-tls"] }
 */
 
+use actix_tls::accept::rustls::TlsStream;
+use actix_web::rt::net::TcpStream;
+use actix_web::dev::{Extensions, Service, ServiceRequest};
+use actix_web::http::header::{HeaderName, HeaderValue};
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tokio::prelude::*;
-use sqlx::PgPool;
-use argon2::{Argon2, PasswordHasher};
+use serde_json::Value;
+use sqlx::postgres::{PgPoolOptions, PgRow};
+use sqlx::types::Json;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{PgPool, Row};
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use chrono::{DateTime, Datelike, NaiveDate, SubsecRound, TimeZone, Utc};
+use rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+use std::any::Any;
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::io::BufReader;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
 use uuid::Uuid;
+use async_trait::async_trait;
+use futures::future::join_all;
+use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials as SmtpCredentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Algorithm identifier recorded in every EncryptedEvent
+const ENCRYPTION_ALGORITHM: &str = "AES-256-GCM";
 
 // Configuration struct
+#[derive(Clone)]
 struct Config {
     api_key: String,
     db_url: String,
+    // Backend holding API keys
+    key_store: KeyStoreKind,
+    // Keys added to the key store at startup if missing
+    seed_keys: Vec<SeedKey>,
+    // Maximum number of pooled database connections
+    db_max_connections: u32,
+    // How long a request waits for a pooled connection before failing
+    db_acquire_timeout: Duration,
+    // Path to the key-encryption key file read by LocalKeyProvider
+    key_file: String,
+    // Path to the Ed25519 notification signing key file
+    signing_key_file: String,
+    // Event type -> JSON paths encrypted individually
+    field_encryption: HashMap<String, Vec<String>>,
+    // Paths encrypted for event types without an entry above. `$` encrypts the
+    // whole payload, so channel templates can only use id, event_type and
+    // recipient for them; give an event type a policy to render its data.
+    default_field_encryption: Vec<String>,
+    // Accept OIDC bearer tokens when set
+    jwt: Option<JwtConfig>,
+    // Address the HttpServer listens on
+    bind_address: String,
+    // Serve HTTPS, optionally with client certificates, when set
+    tls: Option<TlsConfig>,
+    // Limits for callers whose key sets none
+    default_rate_limits: RateLimits,
+    // Limits per source IP on /api/notify; None disables them
+    ip_rate_limits: Option<RateLimits>,
+    // How long API key lookups are cached. Revocations made through another
+    // process (e.g. `revoke-key`) take up to this long to apply.
+    auth_cache_ttl: Duration,
+    // Maximum number of cached API key lookups
+    auth_cache_capacity: usize,
+    // How long, and how many, keys that matched nothing are remembered, so
+    // repeated bad keys don't each reach the KeyStore. Kept short, since a
+    // key issued through another process is refused until its entry expires.
+    rejected_key_cache_ttl: Duration,
+    rejected_key_cache_capacity: usize,
+    // Delays and bans after failed API key attempts
+    lockout: LockoutPolicy,
+    // JSON-lines copy of the audit log, in addition to Postgres
+    audit_log_file: Option<String>,
+    // Tenant id -> per-tenant settings; tenants not listed use the defaults
+    tenants: HashMap<String, TenantConfig>,
+    // Event type -> channels its notifications are sent through
+    channel_routes: HashMap<String, Vec<String>>,
+    // Channels for event types without a route
+    default_channels: Vec<String>,
+    webhooks: WebhookConfig,
+    // SMTP relay for the email channel; None disables it
+    email: Option<EmailConfig>,
+    // Slack, Teams and Discord incoming-webhook channels
+    chat_channels: Vec<ChatChannelConfig>,
+    // HTTP SMS provider for the sms channel; None disables it
+    sms: Option<SmsConfig>,
+}
+
+// API key installed at startup, e.g. into the in-memory key store
+#[derive(Clone)]
+struct SeedKey {
+    // A `prefix.secret` key as printed by `issue-key`; only its hash is stored
+    key: String,
+    tenant_id: String,
+    scopes: Vec<String>,
+}
+
+// Settings for one tenant
+#[derive(Clone, Default)]
+struct TenantConfig {
+    // Own key-encryption key file; None shares `Config.key_file`
+    key_file: Option<String>,
+    // Limits for this tenant's keys that set none, before the global defaults
+    rate_limits: RateLimits,
+    // Channel name -> credentials used to deliver this tenant's notifications
+    channel_credentials: HashMap<String, HashMap<String, String>>,
 }
 
+// Tenant of API keys and data created before tenants existed
+const DEFAULT_TENANT: &str = "default";
+
+// Scopes checked by the gateway
+const SCOPE_NOTIFY_SEND: &str = "notify:send";
+const SCOPE_NOTIFY_READ_STATUS: &str = "notify:read-status";
+const SCOPE_RECIPIENT_KEYS: &str = "recipients:keys";
+const SCOPE_KEYS_ADMIN: &str = "keys:admin";
+// Lets a keys:admin principal manage the keys of every tenant
+const SCOPE_TENANTS_ADMIN: &str = "tenants:admin";
+const SCOPE_WEBHOOKS: &str = "webhooks:manage";
+
 // API Gateway
-async fn api_gateway(req: web::HttpRequest) -> impl Responder {
-    // Get API key from request headers
-    let api_key = req.headers().get("API-KEY");
-
-    // Verify API key
-    match api_key {
-        Some(key) => {
-            // Authenticate API key
-            let is_authenticated = api_key_authenticator(key.to_string()).await;
-
-            if is_authenticated {
-                // Route request to notifier service
-                notifier_service(req).await
-            } else {
-                HttpResponse::Unauthorized().finish()
+async fn api_gateway(
+    req: web::HttpRequest,
+    body: web::Bytes,
+    event_store: web::Data<Arc<dyn EventStore>>,
+    auth: web::Data<Authenticator>,
+    rate_limiter: web::Data<RateLimiter>,
+) -> impl Responder {
+    // Throttle floods by source before spending anything on authentication
+    if let Err(limited) = rate_limiter.check_ip(&req) {
+        return limited.response();
+    }
+
+    let principal = match authenticate_request(&req, &body, &auth).await {
+        Ok(principal) => principal,
+        Err(e) => return e.response(),
+    };
+
+    // Get event from request body
+    let event: Event = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })),
+    };
+
+    // Keys may be limited to particular event types, e.g. "notify:send:payment.completed"
+    let required_scope = format!("{}:{}", SCOPE_NOTIFY_SEND, event.event_type);
+    if let Err(response) = require_scope(&principal, &required_scope) {
+        auth.audit.record(AuditEntry {
+            principal: Some(principal.subject.clone()),
+            event_id: Some(event.id),
+            outcome: "insufficient_scope".to_string(),
+            ..AuditEntry::for_request(&req, "notification.send")
+        });
+        return response;
+    }
+
+    // Enforce the caller's rate limits and quotas
+    let rate_limit = match rate_limiter.check_principal(&principal) {
+        Ok(status) => status,
+        Err(limited) => {
+            auth.audit.record(AuditEntry {
+                principal: Some(principal.subject.clone()),
+                event_id: Some(event.id),
+                outcome: limited.reason.to_string(),
+                ..AuditEntry::for_request(&req, "notification.send")
+            });
+            return limited.response();
+        }
+    };
+
+    // Route request to notifier service
+    let audit = AuditEntry {
+        principal: Some(principal.subject.clone()),
+        event_id: Some(event.id),
+        details: serde_json::json!({ "event_type": event.event_type, "tenant_id": principal.tenant }),
+        ..AuditEntry::for_request(&req, "notification.send")
+    };
+    let mut response = notifier_service(req, &principal.tenant, event, event_store.get_ref().as_ref()).await;
+    auth.audit.record(AuditEntry {
+        outcome: if response.status().is_success() {
+            "accepted".to_string()
+        } else {
+            response.status().as_u16().to_string()
+        },
+        ..audit
+    });
+
+    if let Some(status) = &rate_limit {
+        add_rate_limit_headers(&mut response, status);
+    }
+    response
+}
+
+// Authentication state shared by every handler
+struct Authenticator {
+    key_store: Arc<dyn KeyStore>,
+    // Wraps and unwraps the per-key secrets used to verify signed requests
+    tenant_keys: Arc<TenantKeys>,
+    nonces: NonceCache,
+    // Recent API key lookups; invalidated when a key is revoked or rotated
+    auth_cache: AuthCache,
+    // Recent API keys that matched no stored key
+    rejected_keys: RejectedKeyCache,
+    // Failed attempts per source IP and key prefix
+    lockout: FailureTracker,
+    audit: AuditLog,
+    last_used: LastUsedTracker,
+    // Verifies bearer tokens; None if they are not accepted
+    jwt: Option<JwtValidator>,
+    // Client certificate identity -> tenant and granted scopes
+    client_identities: HashMap<String, ClientIdentity>,
+}
+
+// Why a request was not authenticated; each maps to a distinct error code
+#[derive(Debug, PartialEq)]
+enum AuthError {
+    MissingKey,
+    InvalidKey,
+    ExpiredKey,
+    RevokedKey,
+    InvalidSignature,
+    StaleRequest,
+    ReplayedRequest,
+    InvalidToken,
+    ExpiredToken,
+    UnknownClientCertificate,
+    // Too many failed attempts from this IP or against this key
+    LockedOut { retry_after_secs: u64 },
+    // Too many recent signed requests to remember every nonce
+    NonceCacheFull,
+    // The KeyStore could not be read, so the key is neither accepted nor rejected
+    KeyStoreUnavailable,
+}
+
+impl AuthError {
+    fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingKey => "missing_api_key",
+            AuthError::InvalidKey => "invalid_api_key",
+            AuthError::ExpiredKey => "api_key_expired",
+            AuthError::RevokedKey => "api_key_revoked",
+            AuthError::InvalidSignature => "invalid_signature",
+            AuthError::StaleRequest => "request_timestamp_out_of_range",
+            AuthError::ReplayedRequest => "replayed_request",
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::ExpiredToken => "token_expired",
+            AuthError::UnknownClientCertificate => "unknown_client_certificate",
+            AuthError::LockedOut { .. } => "too_many_failed_attempts",
+            AuthError::NonceCacheFull => "signed_request_capacity_exceeded",
+            AuthError::KeyStoreUnavailable => "key_store_unavailable",
+        }
+    }
+
+    fn response(&self) -> HttpResponse {
+        match self {
+            AuthError::LockedOut { retry_after_secs } => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_secs.to_string()))
+                .json(serde_json::json!({ "error": self.code(), "retry_after": retry_after_secs })),
+            AuthError::NonceCacheFull => HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", NONCE_CACHE_SWEEP_INTERVAL.as_secs().to_string()))
+                .json(serde_json::json!({ "error": self.code() })),
+            AuthError::KeyStoreUnavailable => HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": self.code() })),
+            _ => HttpResponse::Unauthorized().json(serde_json::json!({ "error": self.code() })),
+        }
+    }
+}
+
+// Authenticate a request and record the outcome in the audit log
+async fn authenticate_request(req: &web::HttpRequest, body: &[u8], auth: &Authenticator) -> Result<Principal, AuthError> {
+    let result = authenticate(req, body, auth).await;
+
+    auth.audit.record(AuditEntry {
+        principal: result.as_ref().ok().map(|principal| principal.subject.clone()),
+        outcome: match &result {
+            Ok(_) => "success".to_string(),
+            Err(e) => e.code().to_string(),
+        },
+        details: serde_json::json!({
+            "ip": req.peer_addr().map(|addr| addr.ip().to_string()),
+            "key_prefix": attempted_key_prefix(req),
+        }),
+        ..AuditEntry::for_request(req, "auth")
+    });
+    result
+}
+
+// The API key prefix a request presents, if any
+fn attempted_key_prefix(req: &web::HttpRequest) -> Option<&str> {
+    let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok());
+    if req.headers().contains_key(REQUEST_SIGNATURE_HEADER) {
+        header(KEY_ID_HEADER)
+    } else {
+        header("API-KEY").and_then(parse_api_key).map(|(prefix, _)| prefix)
+    }
+}
+
+// Verify the bearer token, the request signature, the API key sent in the
+// request headers, or else the client certificate of the TLS connection
+async fn authenticate(req: &web::HttpRequest, body: &[u8], auth: &Authenticator) -> Result<Principal, AuthError> {
+    if let Some(token) = bearer_token(req) {
+        let jwt = auth.jwt.as_ref().ok_or(AuthError::InvalidToken)?;
+        return jwt.authenticate(token);
+    }
+
+    let principal = if req.headers().contains_key(REQUEST_SIGNATURE_HEADER) || req.headers().contains_key("API-KEY") {
+        key_authenticator(req, body, auth).await?
+    } else {
+        // Fall back to the client certificate captured when the connection was accepted
+        let cert = req.conn_data::<ClientCertificate>().ok_or(AuthError::MissingKey)?;
+        return client_certificate_authenticator(cert, &auth.client_identities);
+    };
+
+    // Record the use without waiting on the KeyStore
+    auth.last_used.record(&principal.subject);
+    Ok(principal)
+}
+
+// Verify the request signature or API key, slowing down and then locking out
+// source IPs and key prefixes that keep failing
+async fn key_authenticator(req: &web::HttpRequest, body: &[u8], auth: &Authenticator) -> Result<Principal, AuthError> {
+    let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok());
+    let signed = req.headers().contains_key(REQUEST_SIGNATURE_HEADER);
+
+    let prefix = attempted_key_prefix(req);
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    auth.lockout.check(ip)?;
+
+    let result = if signed {
+        signed_request_authenticator(req, body, auth).await
+    } else {
+        // Verify API key
+        match header("API-KEY") {
+            Some(api_key) => {
+                api_key_authenticator(auth.key_store.as_ref(), &auth.auth_cache, &auth.rejected_keys, api_key.to_string()).await
             }
+            None => Err(AuthError::InvalidKey),
         }
-        None => HttpResponse::Unauthorized().finish(),
+    };
+
+    // Wrong secrets count as failures; expired or revoked keys were still guessed correctly
+    if let Err(AuthError::InvalidKey) | Err(AuthError::InvalidSignature) = &result {
+        let delay = auth.lockout.record_failure(ip, prefix);
+        tokio::time::sleep(delay).await;
     }
+    result
 }
 
-// API Key Authenticator
-async fn api_key_authenticator(api_key: String) -> bool {
-    // Database connection
-    let db_pool = PgPool::connect(&config.db_url)
-        .await
-        .expect("Failed to connect to database");
+// Newly issued API key. `key` and `signing_secret` are shown to the caller
+// once; the key is stored only as a hash and the signing secret only wrapped.
+struct IssuedApiKey {
+    key: String,
+    prefix: String,
+    key_hash: Vec<u8>,
+    // HMAC secret for signed requests
+    signing_secret: [u8; 32],
+}
 
-    // Query database to verify API key
-    let result = sqlx::query("SELECT COUNT(*) FROM api_keys WHERE key = $1")
-        .bind(api_key)
-        .fetch_one(db_pool)
-        .await
-        .expect("Failed to execute query");
+// Issue a `prefix.secret` API key; only the prefix and a hash of the secret are stored
+fn generate_api_key() -> IssuedApiKey {
+    let mut prefix = [0u8; 8];
+    let mut secret = [0u8; 32];
+    let mut signing_secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut prefix);
+    rand::thread_rng().fill_bytes(&mut secret);
+    rand::thread_rng().fill_bytes(&mut signing_secret);
+
+    let prefix = hex::encode(prefix);
+    let secret = base64::encode_config(secret, base64::URL_SAFE_NO_PAD);
+
+    IssuedApiKey {
+        key: format!("{}.{}", prefix, secret),
+        key_hash: hash_api_key_secret(&secret),
+        prefix,
+        signing_secret,
+    }
+}
 
-    result.count > 0
+// Split a presented key into (prefix, secret)
+fn parse_api_key(api_key: &str) -> Option<(&str, &str)> {
+    let (prefix, secret) = api_key.split_once('.')?;
+    if prefix.is_empty() || secret.is_empty() {
+        return None;
+    }
+    Some((prefix, secret))
 }
 
-// Notifier Service
-async fn notifier_service(req: web::HttpRequest) -> impl Responder {
-    // Get event from request body
-    let event: Event = serde_json::from_str(&req.payload).expect("Invalid event");
+// Prefixes of keys issued by generate_api_key: 8 random bytes in hex
+fn is_issued_key_prefix(prefix: &str) -> bool {
+    prefix.len() == 16 && prefix.bytes().all(|b| b.is_ascii_hexdigit())
+}
 
-    // Encrypt sensitive information
-    let encrypted_event = encrypt_event(event).await;
+// Prefix under which the schema migration stored a plaintext key from the
+// original api_keys table; its secret is the whole key
+fn legacy_key_prefix(api_key: &str) -> String {
+    format!("legacy-{}", &hex::encode(Sha256::digest(api_key.as_bytes()))[..16])
+}
 
-    // Send notification
-    send_notification(encrypted_event).await;
+// Secrets are 256 random bits, so a fast hash is enough; no password stretching needed
+fn hash_api_key_secret(secret: &str) -> Vec<u8> {
+    Sha256::digest(secret.as_bytes()).to_vec()
+}
+
+// Compare a presented secret with the stored hash in constant time
+fn verify_api_key_secret(secret: &str, stored_hash: Option<&[u8]>) -> bool {
+    let presented = hash_api_key_secret(secret);
+
+    match stored_hash {
+        Some(stored) => presented.ct_eq(stored).into(),
+        None => {
+            // Unknown prefix: do the same work so timing doesn't reveal which prefixes exist
+            let _ = presented.ct_eq(&[0u8; 32][..]);
+            false
+        }
+    }
+}
 
-    HttpResponse::Ok().finish()
+// Stored API key: the lookup prefix and a hash of the secret, never the secret itself
+#[derive(Clone)]
+struct ApiKeyRecord {
+    prefix: String,
+    key_hash: Vec<u8>,
+    // Permissions granted to the key, e.g. "notify:send"
+    scopes: Vec<String>,
+    issued_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    // Updated in the background, so it may lag real use by a flush interval
+    last_used_at: Option<DateTime<Utc>>,
+    // Signing secret for HMAC-signed requests, wrapped by the tenant's KeyProvider
+    signing_key: Option<WrappedKey>,
+    rate_limits: RateLimits,
+    tenant_id: String,
 }
 
-// Encryption Service
-async fn encrypt_event(event: Event) -> EncryptedEvent {
-    // Initialize argon2 password hasher
-    let hasher = Argon2::default();
+impl ApiKeyRecord {
+    fn from_issued(
+        issued: &IssuedApiKey,
+        tenant_id: &str,
+        keys: &dyn KeyProvider,
+        scopes: Vec<String>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        ApiKeyRecord {
+            prefix: issued.prefix.clone(),
+            key_hash: issued.key_hash.clone(),
+            scopes,
+            issued_at: Utc::now(),
+            expires_at,
+            revoked_at: None,
+            last_used_at: None,
+            signing_key: Some(keys.wrap_key(&issued.signing_secret).expect("Failed to wrap signing secret")),
+            rate_limits: RateLimits::default(),
+            tenant_id: tenant_id.to_string(),
+        }
+    }
+
+    fn from_pg_row(row: &PgRow) -> Self {
+        let signing_key: Option<Json<WrappedKey>> = row.get("signing_key");
+        let Json(rate_limits) = row.get("rate_limits");
+
+        ApiKeyRecord {
+            prefix: row.get("prefix"),
+            key_hash: row.get("key_hash"),
+            scopes: row.get("scopes"),
+            issued_at: row.get("issued_at"),
+            expires_at: row.get("expires_at"),
+            revoked_at: row.get("revoked_at"),
+            last_used_at: row.get("last_used_at"),
+            signing_key: signing_key.map(|Json(wrapped)| wrapped),
+            rate_limits,
+            tenant_id: row.get("tenant_id"),
+        }
+    }
 
-    // Hash event data
-    let hashed_data = hasher.hash_password(event.data.as_bytes(), &rand::thread_rng())
-        .expect("Failed to hash event data");
+    // SQLite has no array type, so scopes are stored as a JSON array
+    fn from_sqlite_row(row: &SqliteRow) -> Self {
+        let Json(scopes) = row.get("scopes");
+        let signing_key: Option<Json<WrappedKey>> = row.get("signing_key");
+        let Json(rate_limits) = row.get("rate_limits");
 
-    EncryptedEvent {
-        id: Uuid::new_v4(),
-        data: hashed_data,
+        ApiKeyRecord {
+            prefix: row.get("prefix"),
+            key_hash: row.get("key_hash"),
+            scopes,
+            issued_at: row.get("issued_at"),
+            expires_at: row.get("expires_at"),
+            revoked_at: row.get("revoked_at"),
+            last_used_at: row.get("last_used_at"),
+            signing_key: signing_key.map(|Json(wrapped)| wrapped),
+            rate_limits,
+            tenant_id: row.get("tenant_id"),
+        }
     }
 }
 
-// Send Notification
-async fn send_notification(event: EncryptedEvent) {
-    // Send notification using notification service
-    // ...
+// Key Store: where API key records live
+#[async_trait]
+trait KeyStore: Send + Sync {
+    async fn find_by_prefix(&self, prefix: &str) -> Result<Option<ApiKeyRecord>, sqlx::Error>;
+
+    async fn insert(&self, record: &ApiKeyRecord) -> Result<(), sqlx::Error>;
+
+    // Every key, newest first
+    async fn list(&self) -> Result<Vec<ApiKeyRecord>, sqlx::Error>;
+
+    // Mark a key revoked; returns false if it doesn't exist or is already revoked
+    async fn revoke(&self, prefix: &str) -> Result<bool, sqlx::Error>;
+
+    // Change when a key expires; returns false if it doesn't exist
+    async fn set_expiry(&self, prefix: &str, expires_at: Option<DateTime<Utc>>) -> Result<bool, sqlx::Error>;
+
+    // Advance last_used_at, never moving it backwards
+    async fn record_last_used(&self, prefix: &str, used_at: DateTime<Utc>) -> Result<(), sqlx::Error>;
 }
 
-// Event struct
-struct Event {
-    id: Uuid,
-    data: String,
+// Which KeyStore backend to use
+#[derive(Clone)]
+enum KeyStoreKind {
+    // The api_keys table in the shared Postgres database
+    Postgres,
+    // A SQLite database, e.g. "sqlite://keys.db" or "sqlite::memory:"
+    Sqlite(String),
+    // Process memory; keys are lost on restart
+    InMemory,
 }
 
-// Encrypted Event struct
-struct EncryptedEvent {
-    id: Uuid,
-    data: String,
+// Postgres Key Store
+struct PostgresKeyStore {
+    pool: PgPool,
 }
 
-// Initialize API Gateway
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let config = Config {
-        api_key: "YOUR_API_KEY".to_string(),
-        db_url: "YOUR_DB_URL".to_string(),
-    };
+#[async_trait]
+impl KeyStore for PostgresKeyStore {
+    async fn find_by_prefix(&self, prefix: &str) -> Result<Option<ApiKeyRecord>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT prefix, key_hash, scopes, issued_at, expires_at, revoked_at, last_used_at, signing_key, rate_limits, tenant_id
+             FROM api_keys WHERE prefix = $1",
+        )
+        .bind(prefix)
+        .fetch_optional(&self.pool)
+        .await?;
 
-    HttpServer::new(move || {
-        App::new()
-            .app_data(web::Data::new(config.clone()))
-            .service(web::resource("/api/notify").route(web::post().to(api_gateway)))
+        Ok(row.as_ref().map(ApiKeyRecord::from_pg_row))
+    }
+
+    async fn insert(&self, record: &ApiKeyRecord) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO api_keys (prefix, key_hash, scopes, issued_at, expires_at, revoked_at, last_used_at, signing_key, rate_limits, tenant_id)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        )
+        .bind(&record.prefix)
+        .bind(&record.key_hash)
+        .bind(&record.scopes)
+        .bind(record.issued_at)
+        .bind(record.expires_at)
+        .bind(record.revoked_at)
+        .bind(record.last_used_at)
+        .bind(record.signing_key.as_ref().map(Json))
+        .bind(Json(&record.rate_limits))
+        .bind(&record.tenant_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ApiKeyRecord>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT prefix, key_hash, scopes, issued_at, expires_at, revoked_at, last_used_at, signing_key, rate_limits, tenant_id
+             FROM api_keys ORDER BY issued_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(ApiKeyRecord::from_pg_row).collect())
+    }
+
+    async fn revoke(&self, prefix: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE api_keys SET revoked_at = now() WHERE prefix = $1 AND revoked_at IS NULL")
+            .bind(prefix)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_expiry(&self, prefix: &str, expires_at: Option<DateTime<Utc>>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE api_keys SET expires_at = $2 WHERE prefix = $1")
+            .bind(prefix)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_last_used(&self, prefix: &str, used_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE api_keys SET last_used_at = $2
+             WHERE prefix = $1 AND (last_used_at IS NULL OR last_used_at < $2)",
+        )
+        .bind(prefix)
+        .bind(used_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Columns added to the SQLite api_keys table after it was first created.
+// Keys from before issue times were tracked count as issued at the epoch.
+const SQLITE_API_KEY_COLUMNS: &[(&str, &str)] = &[
+    ("scopes", "TEXT NOT NULL DEFAULT '[]'"),
+    ("issued_at", "TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z'"),
+    ("expires_at", "TEXT"),
+    ("revoked_at", "TEXT"),
+    ("last_used_at", "TEXT"),
+    ("signing_key", "TEXT"),
+    ("rate_limits", "TEXT NOT NULL DEFAULT '{}'"),
+    ("tenant_id", "TEXT NOT NULL DEFAULT 'default'"),
+];
+
+// SQLite Key Store, for development and CI without a database server
+struct SqliteKeyStore {
+    pool: SqlitePool,
+}
+
+impl SqliteKeyStore {
+    async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let pool = SqlitePoolOptions::new().connect(url).await?;
+
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS api_keys (
+                prefix TEXT PRIMARY KEY,
+                key_hash BLOB NOT NULL,
+                scopes TEXT NOT NULL DEFAULT '[]',
+                issued_at TEXT NOT NULL,
+                expires_at TEXT,
+                revoked_at TEXT,
+                last_used_at TEXT,
+                signing_key TEXT,
+                rate_limits TEXT NOT NULL DEFAULT '{}',
+                tenant_id TEXT NOT NULL DEFAULT 'default'
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        // Add columns newer than an existing table; SQLite has no ADD COLUMN IF NOT EXISTS
+        let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('api_keys')")
+            .fetch_all(&pool)
+            .await?;
+        for (column, definition) in SQLITE_API_KEY_COLUMNS {
+            if !columns.iter().any(|existing| existing == column) {
+                sqlx::query(&format!("ALTER TABLE api_keys ADD COLUMN {} {}", column, definition))
+                    .execute(&pool)
+                    .await?;
+            }
+        }
+
+        Ok(SqliteKeyStore { pool })
+    }
+}
+
+#[async_trait]
+impl KeyStore for SqliteKeyStore {
+    async fn find_by_prefix(&self, prefix: &str) -> Result<Option<ApiKeyRecord>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT prefix, key_hash, scopes, issued_at, expires_at, revoked_at, last_used_at, signing_key, rate_limits, tenant_id
+             FROM api_keys WHERE prefix = ?",
+        )
+        .bind(prefix)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(ApiKeyRecord::from_sqlite_row))
+    }
+
+    async fn insert(&self, record: &ApiKeyRecord) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO api_keys (prefix, key_hash, scopes, issued_at, expires_at, revoked_at, last_used_at, signing_key, rate_limits, tenant_id)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&record.prefix)
+        .bind(&record.key_hash)
+        .bind(Json(&record.scopes))
+        .bind(record.issued_at)
+        .bind(record.expires_at)
+        .bind(record.revoked_at)
+        .bind(record.last_used_at)
+        .bind(record.signing_key.as_ref().map(Json))
+        .bind(Json(&record.rate_limits))
+        .bind(&record.tenant_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ApiKeyRecord>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT prefix, key_hash, scopes, issued_at, expires_at, revoked_at, last_used_at, signing_key, rate_limits, tenant_id
+             FROM api_keys ORDER BY issued_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(ApiKeyRecord::from_sqlite_row).collect())
+    }
+
+    async fn revoke(&self, prefix: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE prefix = ? AND revoked_at IS NULL")
+            .bind(Utc::now())
+            .bind(prefix)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_expiry(&self, prefix: &str, expires_at: Option<DateTime<Utc>>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE api_keys SET expires_at = ? WHERE prefix = ?")
+            .bind(expires_at)
+            .bind(prefix)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_last_used(&self, prefix: &str, used_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE api_keys SET last_used_at = ?
+             WHERE prefix = ? AND (last_used_at IS NULL OR last_used_at < ?)",
+        )
+        .bind(used_at)
+        .bind(prefix)
+        .bind(used_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// In-memory Key Store, used in tests
+#[derive(Default)]
+struct InMemoryKeyStore {
+    keys: RwLock<HashMap<String, ApiKeyRecord>>,
+}
+
+#[async_trait]
+impl KeyStore for InMemoryKeyStore {
+    async fn find_by_prefix(&self, prefix: &str) -> Result<Option<ApiKeyRecord>, sqlx::Error> {
+        Ok(self.keys.read().expect("Key store lock poisoned").get(prefix).cloned())
+    }
+
+    async fn insert(&self, record: &ApiKeyRecord) -> Result<(), sqlx::Error> {
+        let mut keys = self.keys.write().expect("Key store lock poisoned");
+        if keys.contains_key(&record.prefix) {
+            return Err(sqlx::Error::Protocol(format!("duplicate API key prefix {}", record.prefix)));
+        }
+
+        keys.insert(record.prefix.clone(), record.clone());
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ApiKeyRecord>, sqlx::Error> {
+        let mut records: Vec<ApiKeyRecord> = self.keys.read().expect("Key store lock poisoned").values().cloned().collect();
+        records.sort_by(|a, b| b.issued_at.cmp(&a.issued_at));
+        Ok(records)
+    }
+
+    async fn revoke(&self, prefix: &str) -> Result<bool, sqlx::Error> {
+        let mut keys = self.keys.write().expect("Key store lock poisoned");
+        match keys.get_mut(prefix) {
+            Some(record) if record.revoked_at.is_none() => {
+                record.revoked_at = Some(Utc::now());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn set_expiry(&self, prefix: &str, expires_at: Option<DateTime<Utc>>) -> Result<bool, sqlx::Error> {
+        let mut keys = self.keys.write().expect("Key store lock poisoned");
+        match keys.get_mut(prefix) {
+            Some(record) => {
+                record.expires_at = expires_at;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn record_last_used(&self, prefix: &str, used_at: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        let mut keys = self.keys.write().expect("Key store lock poisoned");
+        if let Some(record) = keys.get_mut(prefix) {
+            if record.last_used_at.map_or(true, |last| last < used_at) {
+                record.last_used_at = Some(used_at);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Capacity of the last-used queue; updates beyond it are dropped, not awaited
+const LAST_USED_QUEUE_SIZE: usize = 10_000;
+
+// How often batched last-used timestamps are written to the KeyStore
+const LAST_USED_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+// Last-used Tracker: records key use off the request path, batching writes
+#[derive(Clone)]
+struct LastUsedTracker {
+    sender: mpsc::Sender<(String, DateTime<Utc>)>,
+}
+
+impl LastUsedTracker {
+    fn start(key_store: Arc<dyn KeyStore>) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<(String, DateTime<Utc>)>(LAST_USED_QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            // Only the latest use per key matters, so collapse updates between flushes
+            let mut pending: HashMap<String, DateTime<Utc>> = HashMap::new();
+            let mut interval = tokio::time::interval(LAST_USED_FLUSH_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    received = receiver.recv() => match received {
+                        Some((prefix, used_at)) => {
+                            pending.insert(prefix, used_at);
+                        }
+                        None => break,
+                    },
+                    _ = interval.tick() => flush_last_used(key_store.as_ref(), &mut pending).await,
+                }
+            }
+
+            flush_last_used(key_store.as_ref(), &mut pending).await;
+        });
+
+        LastUsedTracker { sender }
+    }
+
+    // Never waits: if the queue is full the update is dropped
+    fn record(&self, prefix: &str) {
+        let _ = self.sender.try_send((prefix.to_string(), Utc::now()));
+    }
+}
+
+async fn flush_last_used(key_store: &dyn KeyStore, pending: &mut HashMap<String, DateTime<Utc>>) {
+    for (prefix, used_at) in pending.drain() {
+        if let Err(e) = key_store.record_last_used(&prefix, used_at).await {
+            eprintln!("Failed to record last use of API key {}: {}", prefix, e);
+        }
+    }
+}
+
+// Open the KeyStore selected in Config
+async fn open_key_store(config: &Config, db_pool: Option<&PgPool>) -> Result<Arc<dyn KeyStore>, sqlx::Error> {
+    Ok(match &config.key_store {
+        KeyStoreKind::Postgres => Arc::new(PostgresKeyStore {
+            pool: db_pool.expect("The Postgres key store connects at startup").clone(),
+        }),
+        KeyStoreKind::Sqlite(url) => Arc::new(SqliteKeyStore::connect(url).await?),
+        KeyStoreKind::InMemory => Arc::new(InMemoryKeyStore::default()),
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
+}
+
+// Authenticated caller and the scopes it was granted
+struct Principal {
+    // API key prefix, or the `sub` claim of a bearer token
+    subject: String,
+    // Tenant the caller acts for; it only sees that tenant's data
+    tenant: String,
+    scopes: Vec<String>,
+    // Limits set on the caller's API key; others get the Config defaults
+    rate_limits: RateLimits,
+}
+
+impl Principal {
+    fn from_record(record: ApiKeyRecord) -> Self {
+        Principal {
+            subject: record.prefix,
+            tenant: record.tenant_id,
+            scopes: record.scopes,
+            rate_limits: record.rate_limits,
+        }
+    }
+
+    // A granted scope covers itself and everything beneath it, so "notify:send"
+    // covers "notify:send:payment.completed"; a trailing "*" matches any suffix
+    fn has_scope(&self, required: &str) -> bool {
+        self.scopes.iter().any(|granted| match granted.strip_suffix('*') {
+            Some(prefix) => required.starts_with(prefix),
+            None => {
+                required == granted
+                    || (required.starts_with(granted.as_str()) && required[granted.len()..].starts_with(':'))
+            }
+        })
+    }
+}
+
+// Reject a principal lacking `scope` with a 403 naming the missing scope
+fn require_scope(principal: &Principal, scope: &str) -> Result<(), HttpResponse> {
+    if principal.has_scope(scope) {
+        Ok(())
+    } else {
+        Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "insufficient_scope",
+            "missing_scope": scope,
+        })))
+    }
+}
+
+// Answer a failed storage call: 503 if the database is unreachable, else 500.
+// The error itself is logged, not sent to the caller.
+fn storage_error(context: &str, e: sqlx::Error) -> HttpResponse {
+    eprintln!("{}: {}", context, e);
+    match e {
+        sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) => {
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": "storage_unavailable" }))
+        }
+        _ => HttpResponse::InternalServerError().json(serde_json::json!({ "error": "storage_error" })),
+    }
+}
+
+// Answer a request for data only Postgres holds when the service runs without it
+fn storage_disabled() -> HttpResponse {
+    HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": "storage_disabled" }))
+}
+
+// A unique constraint violation, e.g. an event id the tenant already used
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => e.code().as_deref() == Some("23505"),
+        _ => false,
+    }
+}
+
+// API Key Authenticator
+async fn api_key_authenticator(
+    key_store: &dyn KeyStore,
+    cache: &AuthCache,
+    rejected: &RejectedKeyCache,
+    api_key: String,
+) -> Result<Principal, AuthError> {
+    let digest = AuthCache::digest(&api_key);
+    let record = match cache.get(&digest) {
+        Some(record) => record,
+        None if rejected.contains(&digest) => return Err(AuthError::InvalidKey),
+        None => match find_api_key(key_store, &api_key).await? {
+            Some(record) => {
+                cache.insert(digest, record.clone());
+                record
+            }
+            None => {
+                rejected.insert(digest);
+                return Err(AuthError::InvalidKey);
+            }
+        },
+    };
+
+    // Lifecycle checks only after the secret matched, so they reveal nothing to guessers
+    check_key_lifecycle(&record)?;
+    Ok(Principal::from_record(record))
+}
+
+// Look up the key record and verify the presented secret against it
+async fn find_api_key(key_store: &dyn KeyStore, api_key: &str) -> Result<Option<ApiKeyRecord>, AuthError> {
+    // Split the key into its lookup prefix and secret; keys from before
+    // prefixes existed are looked up by a prefix derived from the whole key
+    let legacy_prefix;
+    let (prefix, secret) = match parse_api_key(api_key).filter(|(prefix, _)| is_issued_key_prefix(prefix)) {
+        Some(parts) => parts,
+        None => {
+            legacy_prefix = legacy_key_prefix(api_key);
+            (legacy_prefix.as_str(), api_key)
+        }
+    };
+
+    // Look up the stored hash by prefix, then verify the secret against it
+    let record = key_store.find_by_prefix(prefix).await.map_err(|e| {
+        eprintln!("Failed to look up API key: {}", e);
+        AuthError::KeyStoreUnavailable
+    })?;
+
+    if !verify_api_key_secret(secret, record.as_ref().map(|record| record.key_hash.as_slice())) {
+        return Ok(None);
+    }
+    Ok(record)
+}
+
+// Reject revoked and expired keys
+fn check_key_lifecycle(record: &ApiKeyRecord) -> Result<(), AuthError> {
+    if record.revoked_at.is_some() {
+        return Err(AuthError::RevokedKey);
+    }
+    if record.expires_at.map_or(false, |expires_at| expires_at <= Utc::now()) {
+        return Err(AuthError::ExpiredKey);
+    }
+    Ok(())
+}
+
+// Auth Cache: recently verified API keys, keyed by a SHA-256 of the presented
+// key so no plaintext key is held in memory. Hits skip the KeyStore. Invalid
+// keys go to the RejectedKeyCache instead, so guesses can't push out valid
+// entries. Lifecycle checks still run on every hit, so expiry takes effect on time.
+struct AuthCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<AuthCacheEntries>,
+}
+
+#[derive(Default)]
+struct AuthCacheEntries {
+    records: HashMap<[u8; 32], CachedAuth>,
+    // Insertion order. Every entry has the same TTL, so this is also expiry
+    // order; entries replaced or invalidated since are skipped when popped.
+    order: VecDeque<([u8; 32], Instant)>,
+}
+
+struct CachedAuth {
+    record: ApiKeyRecord,
+    cached_at: Instant,
+}
+
+impl AuthCache {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        AuthCache {
+            ttl,
+            capacity,
+            entries: Mutex::new(AuthCacheEntries::default()),
+        }
+    }
+
+    fn digest(api_key: &str) -> [u8; 32] {
+        Sha256::digest(api_key.as_bytes()).into()
+    }
+
+    fn get(&self, digest: &[u8; 32]) -> Option<ApiKeyRecord> {
+        let entries = self.entries.lock().expect("Auth cache lock poisoned");
+        entries
+            .records
+            .get(digest)
+            .filter(|entry| entry.cached_at.elapsed() < self.ttl)
+            .map(|entry| entry.record.clone())
+    }
+
+    fn insert(&self, digest: [u8; 32], record: ApiKeyRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("Auth cache lock poisoned");
+        let AuthCacheEntries { records, order } = &mut *entries;
+
+        // Drop expired entries, then the oldest until there is room
+        while let Some(&(oldest, cached_at)) = order.front() {
+            if records.len() < self.capacity && cached_at.elapsed() < self.ttl {
+                break;
+            }
+            order.pop_front();
+            if records.get(&oldest).map_or(false, |entry| entry.cached_at == cached_at) {
+                records.remove(&oldest);
+            }
+        }
+
+        let cached_at = Instant::now();
+        records.insert(digest, CachedAuth { record, cached_at });
+        order.push_back((digest, cached_at));
+    }
+
+    // Forget a key whose lifecycle changed, so the change applies immediately
+    fn invalidate(&self, prefix: &str) {
+        let mut entries = self.entries.lock().expect("Auth cache lock poisoned");
+        entries.records.retain(|_, entry| entry.record.prefix != prefix);
+    }
+}
+
+// Rejected Key Cache: digests (as in AuthCache) of recently presented keys
+// that matched no stored key, answered without a KeyStore lookup. Bounded and
+// separate from the AuthCache, so a flood of bad keys only evicts its own
+// entries. Newly issued keys are random, so they are never found here.
+struct RejectedKeyCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<RejectedKeyEntries>,
+}
+
+#[derive(Default)]
+struct RejectedKeyEntries {
+    rejected_at: HashMap<[u8; 32], Instant>,
+    // Insertion order, which is also expiry order
+    order: VecDeque<([u8; 32], Instant)>,
+}
+
+impl RejectedKeyCache {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        RejectedKeyCache {
+            ttl,
+            capacity,
+            entries: Mutex::new(RejectedKeyEntries::default()),
+        }
+    }
+
+    fn contains(&self, digest: &[u8; 32]) -> bool {
+        let entries = self.entries.lock().expect("Rejected key cache lock poisoned");
+        entries
+            .rejected_at
+            .get(digest)
+            .map_or(false, |rejected_at| rejected_at.elapsed() < self.ttl)
+    }
+
+    fn insert(&self, digest: [u8; 32]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("Rejected key cache lock poisoned");
+        let RejectedKeyEntries { rejected_at, order } = &mut *entries;
+
+        // Drop expired entries, then the oldest until there is room
+        while let Some(&(oldest, at)) = order.front() {
+            if rejected_at.len() < self.capacity && at.elapsed() < self.ttl {
+                break;
+            }
+            order.pop_front();
+            if rejected_at.get(&oldest) == Some(&at) {
+                rejected_at.remove(&oldest);
+            }
+        }
+
+        let now = Instant::now();
+        rejected_at.insert(digest, now);
+        order.push_back((digest, now));
+    }
+}
+
+// Failed-attempt thresholds for API keys and signed requests
+#[derive(Clone)]
+struct LockoutPolicy {
+    // Failures are counted from the first one in this window
+    window: Duration,
+    // Failures answered without delay
+    delay_after: u32,
+    // Delay after the first delayed failure; doubles with each further one
+    base_delay: Duration,
+    max_delay: Duration,
+    // Failures that trigger a temporary ban
+    ban_after: u32,
+    ban_duration: Duration,
+}
+
+// Tracked subject count at which stale entries are swept
+const FAILURE_TRACKER_SWEEP_THRESHOLD: usize = 100_000;
+
+// Failed attempts by one source IP, or against one key prefix from anywhere
+struct FailureRecord {
+    failures: u32,
+    window_start: Instant,
+    banned_until: Option<Instant>,
+}
+
+// Failure Tracker: counts failed authentication attempts per source IP,
+// delaying and then temporarily banning repeat offenders, and per key prefix,
+// so guesses spread over many IPs are slowed down too. Prefixes are public, so
+// a prefix is never banned; otherwise anyone could lock a key's owner out.
+// Delays only hold failed attempts, so the owner's requests are never slowed.
+struct FailureTracker {
+    policy: LockoutPolicy,
+    entries: Mutex<HashMap<String, FailureRecord>>,
+}
+
+impl FailureTracker {
+    fn new(policy: LockoutPolicy) -> Self {
+        FailureTracker {
+            policy,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn subjects(ip: Option<IpAddr>, prefix: Option<&str>) -> Vec<(&'static str, String)> {
+        let mut subjects = Vec::new();
+        if let Some(ip) = ip {
+            subjects.push(("ip", ip.to_string()));
+        }
+        if let Some(prefix) = prefix {
+            subjects.push(("key_prefix", prefix.to_string()));
+        }
+        subjects
+    }
+
+    // Refuse attempts from a banned IP
+    fn check(&self, ip: Option<IpAddr>) -> Result<(), AuthError> {
+        let now = Instant::now();
+        let entries = self.entries.lock().expect("Failure tracker lock poisoned");
+
+        let banned_until = ip
+            .and_then(|ip| entries.get(&format!("ip:{}", ip)))
+            .and_then(|record| record.banned_until)
+            .filter(|banned_until| *banned_until > now);
+        match banned_until {
+            Some(banned_until) => Err(AuthError::LockedOut {
+                retry_after_secs: (banned_until - now).as_secs().max(1),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Count a failure and return how long to hold the response
+    fn record_failure(&self, ip: Option<IpAddr>, prefix: Option<&str>) -> Duration {
+        let now = Instant::now();
+        let policy = &self.policy;
+        let mut entries = self.entries.lock().expect("Failure tracker lock poisoned");
+
+        if entries.len() >= FAILURE_TRACKER_SWEEP_THRESHOLD {
+            entries.retain(|_, record| {
+                record.banned_until.map_or(false, |banned_until| banned_until > now)
+                    || now.duration_since(record.window_start) < policy.window
+            });
+        }
+
+        let mut delay = Duration::from_secs(0);
+        for (kind, subject) in Self::subjects(ip, prefix) {
+            let record = entries.entry(format!("{}:{}", kind, subject)).or_insert(FailureRecord {
+                failures: 0,
+                window_start: now,
+                banned_until: None,
+            });
+
+            // Start a new window once the old one has passed
+            if now.duration_since(record.window_start) >= policy.window {
+                record.failures = 0;
+                record.window_start = now;
+            }
+            record.failures += 1;
+
+            // Progressive delay, doubling per failure past the threshold
+            if record.failures > policy.delay_after {
+                let doublings = (record.failures - policy.delay_after - 1).min(16);
+                let subject_delay = policy
+                    .base_delay
+                    .checked_mul(1 << doublings)
+                    .map_or(policy.max_delay, |d| d.min(policy.max_delay));
+                delay = delay.max(subject_delay);
+            }
+
+            // Ban an IP once the threshold is reached; attempts during a ban are
+            // refused before they are counted, so this fires once per ban. A
+            // prefix is only reported, once per window.
+            let banned_for = match kind {
+                "ip" if record.failures >= policy.ban_after => policy.ban_duration,
+                "key_prefix" if record.failures == policy.ban_after => Duration::ZERO,
+                _ => continue,
+            };
+            if banned_for > Duration::ZERO {
+                record.banned_until = Some(now + banned_for);
+            }
+            emit_security_event(
+                "auth_lockout",
+                serde_json::json!({
+                    kind: subject,
+                    "failures": record.failures,
+                    "banned_for_secs": banned_for.as_secs(),
+                }),
+            );
+        }
+        delay
+    }
+}
+
+// Security events are written to stderr as JSON lines for the log pipeline
+fn emit_security_event(event: &str, details: Value) {
+    eprintln!(
+        "{}",
+        serde_json::json!({
+            "security_event": event,
+            "at": Utc::now(),
+            "details": details,
+        })
+    );
+}
+
+// Headers of an HMAC-signed request, used instead of API-KEY
+const KEY_ID_HEADER: &str = "X-Key-Id";
+const TIMESTAMP_HEADER: &str = "X-Timestamp";
+const NONCE_HEADER: &str = "X-Nonce";
+const REQUEST_SIGNATURE_HEADER: &str = "X-Signature";
+
+// How far a signed request's timestamp may drift from our clock
+const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+// Most nonces remembered at once; signed requests are refused beyond it
+const NONCE_CACHE_CAPACITY: usize = 1_000_000;
+
+// How often expired nonces are swept
+const NONCE_CACHE_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+// Nonce Cache: remembers nonces of accepted signed requests until their
+// timestamps fall outside the skew window, so each can be used only once
+#[derive(Default)]
+struct NonceCache {
+    // (key prefix, nonce) -> unix time after which the entry can be forgotten
+    seen: Mutex<HashMap<(String, String), i64>>,
+}
+
+impl NonceCache {
+    // Fails if the nonce was already used with this key, or if the cache is
+    // full of unexpired nonces and so could not detect a replay later
+    fn check_and_insert(&self, key_prefix: &str, nonce: &str, timestamp: i64) -> Result<(), AuthError> {
+        let now = Utc::now().timestamp();
+        let mut seen = self.seen.lock().expect("Nonce cache lock poisoned");
+
+        let entry = (key_prefix.to_string(), nonce.to_string());
+        match seen.get(&entry) {
+            Some(forget_after) if *forget_after > now => Err(AuthError::ReplayedRequest),
+            Some(_) => {
+                seen.insert(entry, timestamp + MAX_CLOCK_SKEW_SECS);
+                Ok(())
+            }
+            None if seen.len() >= NONCE_CACHE_CAPACITY => Err(AuthError::NonceCacheFull),
+            None => {
+                seen.insert(entry, timestamp + MAX_CLOCK_SKEW_SECS);
+                Ok(())
+            }
+        }
+    }
+
+    // Forget nonces whose timestamps have left the skew window
+    fn sweep(&self) {
+        let now = Utc::now().timestamp();
+        self.seen
+            .lock()
+            .expect("Nonce cache lock poisoned")
+            .retain(|_, forget_after| *forget_after > now);
+    }
+}
+
+// String covered by a request signature
+fn canonical_request(method: &str, path: &str, timestamp: &str, nonce: &str, body: &[u8]) -> String {
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method,
+        path,
+        timestamp,
+        nonce,
+        hex::encode(Sha256::digest(body))
+    )
+}
+
+// Verify an HMAC-SHA256 signed request:
+// X-Signature = base64(HMAC(signing secret, method \n path?query \n timestamp \n nonce \n hex(sha256(body))))
+async fn signed_request_authenticator(
+    req: &web::HttpRequest,
+    body: &[u8],
+    auth: &Authenticator,
+) -> Result<Principal, AuthError> {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(AuthError::InvalidSignature)
+    };
+    let key_prefix = header(KEY_ID_HEADER)?;
+    let timestamp = header(TIMESTAMP_HEADER)?;
+    let nonce = header(NONCE_HEADER)?;
+    let signature = base64::decode(header(REQUEST_SIGNATURE_HEADER)?).map_err(|_| AuthError::InvalidSignature)?;
+
+    // Reject requests outside the clock-skew window
+    let timestamp_secs: i64 = timestamp.parse().map_err(|_| AuthError::StaleRequest)?;
+    if (Utc::now().timestamp() - timestamp_secs).abs() > MAX_CLOCK_SKEW_SECS {
+        return Err(AuthError::StaleRequest);
+    }
+
+    // Recover the key's signing secret
+    let record = auth
+        .key_store
+        .find_by_prefix(key_prefix)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to look up API key: {}", e);
+            AuthError::KeyStoreUnavailable
+        })?
+        .ok_or(AuthError::InvalidSignature)?;
+    let signing_secret = record
+        .signing_key
+        .as_ref()
+        .and_then(|wrapped| auth.tenant_keys.for_tenant(&record.tenant_id).unwrap_key(wrapped).ok())
+        .ok_or(AuthError::InvalidSignature)?;
+
+    // Verify the signature in constant time
+    let path = req.uri().path_and_query().map_or(req.path(), |path| path.as_str());
+    let canonical = canonical_request(req.method().as_str(), path, timestamp, nonce, body);
+    let mut mac = HmacSha256::new_from_slice(&signing_secret).expect("HMAC accepts any key length");
+    mac.update(canonical.as_bytes());
+    mac.verify_slice(&signature).map_err(|_| AuthError::InvalidSignature)?;
+
+    // Only remember nonces of genuine requests, so forgeries can't fill the cache
+    auth.nonces.check_and_insert(&record.prefix, nonce, timestamp_secs)?;
+
+    check_key_lifecycle(&record)?;
+    Ok(Principal::from_record(record))
+}
+
+// Algorithms accepted on bearer tokens. Symmetric algorithms are excluded so a
+// public JWKS key can never be used as an HMAC secret.
+const BEARER_TOKEN_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+// How often the JWKS is re-read to pick up the issuer's key rotations
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+// Bearer token settings
+#[derive(Clone)]
+struct JwtConfig {
+    // Where the issuer publishes its signing keys
+    jwks: JwksSource,
+    // Required `iss` claim
+    issuer: String,
+    // Required `aud` claim
+    audience: String,
+    // Claim naming the caller's tenant; tokens without it are rejected
+    tenant_claim: String,
+}
+
+#[derive(Clone)]
+enum JwksSource {
+    // Local file, for offline testing
+    File(PathBuf),
+    Url(String),
+}
+
+// Why the JWKS could not be loaded
+#[derive(Debug)]
+enum JwksError {
+    Io(std::io::Error),
+    Fetch(reqwest::Error),
+    Parse(serde_json::Error),
+    InvalidKey(String),
+}
+
+impl fmt::Display for JwksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwksError::Io(e) => write!(f, "failed to read JWKS file: {}", e),
+            JwksError::Fetch(e) => write!(f, "failed to fetch JWKS: {}", e),
+            JwksError::Parse(e) => write!(f, "invalid JWKS: {}", e),
+            JwksError::InvalidKey(kid) => write!(f, "unusable key in JWKS: {}", kid),
+        }
+    }
+}
+
+impl std::error::Error for JwksError {}
+
+// Claims read from a bearer token; `exp`, `iss` and `aud` are checked by jsonwebtoken
+#[derive(Deserialize)]
+struct TokenClaims {
+    sub: String,
+    // OAuth2 space-separated scopes
+    #[serde(default)]
+    scope: Option<String>,
+    // Scopes under `scp`, which issuers send either way
+    #[serde(default)]
+    scp: Option<TokenScopes>,
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+// `scp` claim as a space-separated string or an array of scopes
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenScopes {
+    Spaced(String),
+    List(Vec<String>),
+}
+
+impl TokenScopes {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            TokenScopes::Spaced(scopes) => scopes.split_whitespace().map(str::to_string).collect(),
+            TokenScopes::List(scopes) => scopes,
+        }
+    }
+}
+
+// A verification key from the JWKS, pinned to its declared algorithm if any
+struct TokenKey {
+    key: DecodingKey,
+    algorithm: Option<Algorithm>,
+}
+
+// JWT Validator: verifies bearer tokens against the issuer's JWKS
+struct JwtValidator {
+    config: JwtConfig,
+    // kid -> key
+    keys: RwLock<HashMap<String, TokenKey>>,
+}
+
+impl JwtValidator {
+    async fn load(config: JwtConfig) -> Result<Self, JwksError> {
+        let validator = JwtValidator {
+            config,
+            keys: RwLock::new(HashMap::new()),
+        };
+        validator.refresh().await?;
+        Ok(validator)
+    }
+
+    // Re-read the JWKS, replacing the cached keys only if the whole set is usable
+    async fn refresh(&self) -> Result<(), JwksError> {
+        let jwks: JwkSet = match &self.config.jwks {
+            JwksSource::File(path) => {
+                let contents = std::fs::read(path).map_err(JwksError::Io)?;
+                serde_json::from_slice(&contents).map_err(JwksError::Parse)?
+            }
+            JwksSource::Url(url) => reqwest::get(url)
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(JwksError::Fetch)?
+                .json()
+                .await
+                .map_err(JwksError::Fetch)?,
+        };
+
+        let mut keys = HashMap::new();
+        for jwk in &jwks.keys {
+            // Keys without a kid can't be selected by a token header
+            let kid = match &jwk.common.key_id {
+                Some(kid) => kid.clone(),
+                None => continue,
+            };
+            let key = DecodingKey::from_jwk(jwk).map_err(|_| JwksError::InvalidKey(kid.clone()))?;
+            keys.insert(
+                kid,
+                TokenKey {
+                    key,
+                    algorithm: jwk.common.algorithm,
+                },
+            );
+        }
+
+        *self.keys.write().expect("JWKS lock poisoned") = keys;
+        Ok(())
+    }
+
+    // Verify a bearer token and map its claims to a principal
+    fn authenticate(&self, token: &str) -> Result<Principal, AuthError> {
+        let header = decode_header(token).map_err(|_| AuthError::InvalidToken)?;
+        if !BEARER_TOKEN_ALGORITHMS.contains(&header.alg) {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let keys = self.keys.read().expect("JWKS lock poisoned");
+        let token_key = header
+            .kid
+            .as_ref()
+            .and_then(|kid| keys.get(kid))
+            .ok_or(AuthError::InvalidToken)?;
+        if token_key.algorithm.map_or(false, |algorithm| algorithm != header.alg) {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.audience]);
+
+        let claims = decode::<TokenClaims>(token, &token_key.key, &validation)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => AuthError::ExpiredToken,
+                _ => AuthError::InvalidToken,
+            })?
+            .claims;
+
+        // Accept either scope representation
+        let mut scopes = claims.scp.map(TokenScopes::into_vec).unwrap_or_default();
+        if let Some(scope) = &claims.scope {
+            scopes.extend(scope.split_whitespace().map(str::to_string));
+        }
+
+        // Every caller must belong to a tenant
+        let tenant = claims
+            .other
+            .get(&self.config.tenant_claim)
+            .and_then(Value::as_str)
+            .ok_or(AuthError::InvalidToken)?
+            .to_string();
+
+        Ok(Principal {
+            subject: claims.sub,
+            tenant,
+            scopes,
+            rate_limits: RateLimits::default(),
+        })
+    }
+}
+
+// Token from an `Authorization: Bearer` header, if one was sent
+fn bearer_token(req: &web::HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+// Notifier Service
+async fn notifier_service(req: web::HttpRequest, tenant_id: &str, event: Event, event_store: &dyn EventStore) -> HttpResponse {
+    // Get the tenant's key provider
+    let keys = req
+        .app_data::<web::Data<Arc<TenantKeys>>>()
+        .expect("Missing tenant keys")
+        .for_tenant(tenant_id);
+
+    // Look up which fields this event type encrypts
+    let policies = req
+        .app_data::<web::Data<FieldEncryptionPolicies>>()
+        .expect("Missing field encryption policies");
+    let policy = policies.paths_for(&event.event_type);
+
+    // Look up the recipient's public key, if they registered one
+    let recipient_key = match &event.recipient {
+        Some(recipient_id) => match event_store.active_recipient_key(tenant_id, recipient_id).await {
+            Ok(key) => key,
+            Err(e) => return storage_error("Failed to look up recipient key", e),
+        },
+        None => None,
+    };
+
+    // Encrypt sensitive information. Channels that render the event get it with
+    // those fields removed, so they never leave the service in clear.
+    let redacted = redact_event(&event, policy);
+    let encrypted_event = match encrypt_event(event, tenant_id, keys.as_ref(), policy, recipient_key.as_ref()).await {
+        Ok(encrypted_event) => encrypted_event,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to encrypt event: {}", e)
+            }))
+        }
+    };
+
+    // Persist the encrypted event. Event ids are chosen by the client and unique
+    // per tenant, so a retried notification is refused instead of sent twice.
+    if let Err(e) = event_store.store(&encrypted_event).await {
+        if is_unique_violation(&e) {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "duplicate_event",
+                "event_id": encrypted_event.id,
+            }));
+        }
+        return storage_error("Failed to store encrypted event", e);
+    }
+
+    // Sign the delivered payload
+    let signer = req
+        .app_data::<web::Data<NotificationSigner>>()
+        .expect("Missing notification signer");
+    let payload = serde_json::to_vec(&encrypted_event).expect("Failed to serialize encrypted event");
+    let notification = signer.sign(payload);
+
+    // Send notification
+    let channels = req
+        .app_data::<web::Data<ChannelRegistry>>()
+        .expect("Missing channel registry");
+    let deliveries = send_notification(channels, tenant_id, &redacted, &encrypted_event, &notification).await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "event_id": encrypted_event.id,
+        "deliveries": deliveries,
+    }))
+}
+
+// Notification status: whether an event was accepted, and when
+async fn notification_status(
+    req: web::HttpRequest,
+    event_id: web::Path<Uuid>,
+    event_store: web::Data<Arc<dyn EventStore>>,
+    auth: web::Data<Authenticator>,
+) -> HttpResponse {
+    let principal = match authenticate_request(&req, &[], &auth).await {
+        Ok(principal) => principal,
+        Err(e) => return e.response(),
+    };
+    if let Err(response) = require_scope(&principal, SCOPE_NOTIFY_READ_STATUS) {
+        return response;
+    }
+
+    // Events of other tenants are reported as missing
+    let event_id = event_id.into_inner();
+    let accepted = match event_store.accepted(&principal.tenant, event_id).await {
+        Ok(accepted) => accepted,
+        Err(e) => return storage_error("Failed to look up event", e),
+    };
+
+    match accepted {
+        Some((event_type, accepted_at)) => HttpResponse::Ok().json(serde_json::json!({
+            "id": event_id,
+            "event_type": event_type,
+            "status": "accepted",
+            "accepted_at": accepted_at,
+        })),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+// Rate limits and quotas of one API key. Unset fields fall back to the
+// defaults in Config; all unset means unlimited.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct RateLimits {
+    // Sustained rate the token bucket refills at
+    #[serde(default)]
+    requests_per_second: Option<f64>,
+    // Bucket capacity: requests allowed in a burst
+    #[serde(default)]
+    burst: Option<u32>,
+    // Requests allowed per UTC day
+    #[serde(default)]
+    daily_quota: Option<u64>,
+    // Requests allowed per UTC calendar month
+    #[serde(default)]
+    monthly_quota: Option<u64>,
+}
+
+impl RateLimits {
+    // Fill unset fields from `defaults`
+    fn or(&self, defaults: &RateLimits) -> RateLimits {
+        RateLimits {
+            requests_per_second: self.requests_per_second.or(defaults.requests_per_second),
+            burst: self.burst.or(defaults.burst),
+            daily_quota: self.daily_quota.or(defaults.daily_quota),
+            monthly_quota: self.monthly_quota.or(defaults.monthly_quota),
+        }
+    }
+
+    // Reject limits a token bucket can't work with
+    fn validate(&self) -> Result<(), String> {
+        if let Some(rate) = self.requests_per_second {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err("requests_per_second must be a positive number".to_string());
+            }
+        }
+        if self.burst == Some(0) {
+            return Err("burst must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+// How often idle buckets and past quota periods are swept
+const RATE_LIMIT_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// Token bucket for one caller
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    // Refill rate and capacity, so idle buckets can be recognised when sweeping
+    rate: f64,
+    capacity: f64,
+}
+
+// Requests counted against one caller's quotas
+#[derive(Default)]
+struct QuotaUsage {
+    day: Option<NaiveDate>,
+    daily: u64,
+    // (year, month)
+    month: Option<(i32, u32)>,
+    monthly: u64,
+}
+
+// Rate limit state reported back in X-RateLimit-* headers
+struct RateLimitStatus {
+    limit: u32,
+    remaining: u32,
+    // Seconds until the bucket is full again
+    reset_secs: u64,
+}
+
+// Why a request was throttled
+struct RateLimited {
+    reason: &'static str,
+    retry_after_secs: u64,
+    status: Option<RateLimitStatus>,
+}
+
+impl RateLimited {
+    fn response(&self) -> HttpResponse {
+        let mut response = HttpResponse::TooManyRequests();
+        response.insert_header(("Retry-After", self.retry_after_secs.to_string()));
+        if let Some(status) = &self.status {
+            response
+                .insert_header(("X-RateLimit-Limit", status.limit.to_string()))
+                .insert_header(("X-RateLimit-Remaining", status.remaining.to_string()))
+                .insert_header(("X-RateLimit-Reset", status.reset_secs.to_string()));
+        }
+        response.json(serde_json::json!({
+            "error": self.reason,
+            "retry_after": self.retry_after_secs,
+        }))
+    }
+}
+
+// Rate Limiter: token buckets and quota counters per caller, shared by every
+// worker. Counters live in memory, so quotas restart with the process.
+struct RateLimiter {
+    // Limits for keys that set none, and for token and certificate callers
+    defaults: RateLimits,
+    // Tenant id -> limits applied before `defaults`
+    tenant_defaults: HashMap<String, RateLimits>,
+    // Limits per source IP, checked before authentication
+    per_ip: Option<RateLimits>,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+    usage: Mutex<HashMap<String, QuotaUsage>>,
+}
+
+impl RateLimiter {
+    fn new(defaults: RateLimits, tenant_defaults: HashMap<String, RateLimits>, per_ip: Option<RateLimits>) -> Self {
+        RateLimiter {
+            defaults,
+            tenant_defaults,
+            per_ip,
+            buckets: Mutex::new(HashMap::new()),
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Throttle by source IP, if configured
+    fn check_ip(&self, req: &web::HttpRequest) -> Result<Option<RateLimitStatus>, RateLimited> {
+        match (&self.per_ip, req.peer_addr()) {
+            (Some(limits), Some(addr)) => self.check(&format!("ip:{}", addr.ip()), limits),
+            _ => Ok(None),
+        }
+    }
+
+    // Throttle an authenticated caller by its own limits, then its tenant's
+    fn check_principal(&self, principal: &Principal) -> Result<Option<RateLimitStatus>, RateLimited> {
+        let defaults = match self.tenant_defaults.get(&principal.tenant) {
+            Some(tenant_limits) => tenant_limits.or(&self.defaults),
+            None => self.defaults.clone(),
+        };
+        let limits = principal.rate_limits.or(&defaults);
+        self.check(&format!("principal:{}:{}", principal.tenant, principal.subject), &limits)
+    }
+
+    // Take a token from the caller's bucket and count the request against its
+    // quotas. A throttled request consumes nothing.
+    fn check(&self, caller: &str, limits: &RateLimits) -> Result<Option<RateLimitStatus>, RateLimited> {
+        let now = Utc::now();
+        let today = now.date_naive();
+        let this_month = (now.year(), now.month());
+
+        // Callers without quotas keep no usage counters
+        if limits.daily_quota.is_none() && limits.monthly_quota.is_none() {
+            return match limits.requests_per_second {
+                Some(rate) => Ok(Some(self.take_token(caller, rate, limits.burst.unwrap_or(1).max(1))?)),
+                None => Ok(None),
+            };
+        }
+
+        let mut usage = self.usage.lock().expect("Rate limiter lock poisoned");
+        let quota = usage.entry(caller.to_string()).or_default();
+
+        // Start new quota periods
+        if quota.day != Some(today) {
+            quota.day = Some(today);
+            quota.daily = 0;
+        }
+        if quota.month != Some(this_month) {
+            quota.month = Some(this_month);
+            quota.monthly = 0;
+        }
+
+        if limits.daily_quota.map_or(false, |limit| quota.daily >= limit) {
+            return Err(RateLimited {
+                reason: "daily_quota_exceeded",
+                retry_after_secs: seconds_until(next_day(today)),
+                status: None,
+            });
+        }
+        if limits.monthly_quota.map_or(false, |limit| quota.monthly >= limit) {
+            return Err(RateLimited {
+                reason: "monthly_quota_exceeded",
+                retry_after_secs: seconds_until(next_month(this_month)),
+                status: None,
+            });
+        }
+
+        let status = match limits.requests_per_second {
+            Some(rate) => Some(self.take_token(caller, rate, limits.burst.unwrap_or(1).max(1))?),
+            None => None,
+        };
+
+        quota.daily += 1;
+        quota.monthly += 1;
+        Ok(status)
+    }
+
+    fn take_token(&self, caller: &str, rate: f64, burst: u32) -> Result<RateLimitStatus, RateLimited> {
+        let now = Instant::now();
+        let capacity = burst as f64;
+        let mut buckets = self.buckets.lock().expect("Rate limiter lock poisoned");
+
+        let bucket = buckets.entry(caller.to_string()).or_insert(TokenBucket {
+            tokens: capacity,
+            last_refill: now,
+            rate,
+            capacity,
+        });
+        // Limits may have changed since the bucket was created
+        bucket.rate = rate;
+        bucket.capacity = capacity;
+
+        // Refill for the time since the last request
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.last_refill).as_secs_f64() * rate).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens < 1.0 {
+            let status = RateLimitStatus {
+                limit: burst,
+                remaining: 0,
+                reset_secs: ((capacity - bucket.tokens) / rate).ceil() as u64,
+            };
+            return Err(RateLimited {
+                reason: "rate_limited",
+                retry_after_secs: ((1.0 - bucket.tokens) / rate).ceil().max(1.0) as u64,
+                status: Some(status),
+            });
+        }
+
+        bucket.tokens -= 1.0;
+        Ok(RateLimitStatus {
+            limit: burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: ((capacity - bucket.tokens) / rate).ceil() as u64,
+        })
+    }
+
+    // Drop buckets that have refilled completely and counters of past quota
+    // periods; neither holds any state
+    fn sweep(&self) {
+        let now = Instant::now();
+        self.buckets
+            .lock()
+            .expect("Rate limiter lock poisoned")
+            .retain(|_, bucket| bucket.tokens + now.duration_since(bucket.last_refill).as_secs_f64() * bucket.rate < bucket.capacity);
+
+        let today = Utc::now().date_naive();
+        let this_month = (today.year(), today.month());
+        self.usage
+            .lock()
+            .expect("Rate limiter lock poisoned")
+            .retain(|_, quota| quota.day == Some(today) || quota.month == Some(this_month));
+    }
+}
+
+// Start of the UTC day after `day`
+fn next_day(day: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.succ_opt().expect("Date out of range").and_hms_opt(0, 0, 0).expect("Valid time"))
+}
+
+// Start of the UTC month after `(year, month)`
+fn next_month((year, month): (i32, u32)) -> DateTime<Utc> {
+    let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    Utc.from_utc_datetime(
+        &NaiveDate::from_ymd_opt(year, month, 1)
+            .expect("Date out of range")
+            .and_hms_opt(0, 0, 0)
+            .expect("Valid time"),
+    )
+}
+
+fn seconds_until(time: DateTime<Utc>) -> u64 {
+    (time - Utc::now()).num_seconds().max(1) as u64
+}
+
+// Report the caller's bucket state on a response
+fn add_rate_limit_headers(response: &mut HttpResponse, status: &RateLimitStatus) {
+    let headers = response.headers_mut();
+    for (name, value) in [
+        ("x-ratelimit-limit", status.limit.to_string()),
+        ("x-ratelimit-remaining", status.remaining.to_string()),
+        ("x-ratelimit-reset", status.reset_secs.to_string()),
+    ] {
+        headers.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_str(&value).expect("Numeric header value"),
+        );
+    }
+}
+
+// Admin API: request body for creating a key
+#[derive(Deserialize)]
+struct CreateApiKey {
+    scopes: Vec<String>,
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    rate_limits: RateLimits,
+    // Defaults to the admin's own tenant
+    #[serde(default)]
+    tenant_id: Option<String>,
+}
+
+// Admin API: request body for rotating a key
+#[derive(Deserialize, Default)]
+struct RotateApiKey {
+    // Keep the old key valid this long so callers can switch over; 0 revokes it at once
+    #[serde(default)]
+    grace_period_secs: u64,
+    // Expiry of the new key; defaults to the old key's
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+// Longest grace period a rotation may give the old key
+const MAX_ROTATION_GRACE_PERIOD_SECS: u64 = 30 * 24 * 60 * 60;
+
+impl ApiKeyRecord {
+    // Admin view of a key; the hash is never returned
+    fn to_json(&self) -> Value {
+        let status = if self.revoked_at.is_some() {
+            "revoked"
+        } else if self.expires_at.map_or(false, |expires_at| expires_at <= Utc::now()) {
+            "expired"
+        } else {
+            "active"
+        };
+
+        serde_json::json!({
+            "prefix": self.prefix,
+            "tenant_id": self.tenant_id,
+            "scopes": self.scopes,
+            "status": status,
+            "issued_at": self.issued_at,
+            "expires_at": self.expires_at,
+            "revoked_at": self.revoked_at,
+            "last_used_at": self.last_used_at,
+            "rate_limits": self.rate_limits,
+        })
+    }
+}
+
+// Authenticate an admin request, requiring the keys:admin scope
+async fn authenticate_admin(req: &web::HttpRequest, body: &[u8], auth: &Authenticator) -> Result<Principal, HttpResponse> {
+    let principal = authenticate_request(req, body, auth).await.map_err(|e| e.response())?;
+    require_scope(&principal, SCOPE_KEYS_ADMIN)?;
+    Ok(principal)
+}
+
+// Admins manage their own tenant's keys; tenants:admin reaches every tenant
+fn can_manage_tenant(principal: &Principal, tenant_id: &str) -> bool {
+    principal.tenant == tenant_id || principal.has_scope(SCOPE_TENANTS_ADMIN)
+}
+
+// Admins can only hand out scopes they hold themselves, so a tenant's
+// keys:admin can't mint tenants:admin or "*". Answers 403 naming the first
+// scope the principal lacks.
+fn require_grantable(principal: &Principal, scopes: &[String]) -> Result<(), HttpResponse> {
+    scopes.iter().try_for_each(|scope| require_scope(principal, scope))
+}
+
+// Issue a key and return its prefix and the response; the secrets appear only in this response
+async fn issue_api_key(
+    auth: &Authenticator,
+    tenant_id: &str,
+    scopes: Vec<String>,
+    expires_at: Option<DateTime<Utc>>,
+    rate_limits: RateLimits,
+) -> Result<(String, HttpResponse), HttpResponse> {
+    let issued = generate_api_key();
+    let keys = auth.tenant_keys.for_tenant(tenant_id);
+    let mut record = ApiKeyRecord::from_issued(&issued, tenant_id, keys.as_ref(), scopes, expires_at);
+    record.rate_limits = rate_limits;
+    if let Err(e) = auth.key_store.insert(&record).await {
+        return Err(storage_error("Failed to store API key", e));
+    }
+
+    let mut body = record.to_json();
+    body["key"] = Value::String(issued.key);
+    body["signing_secret"] = Value::String(base64::encode(issued.signing_secret));
+    Ok((record.prefix, HttpResponse::Created().json(body)))
+}
+
+// Parse a JSON request body, answering 400 if it is malformed
+fn parse_json_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, HttpResponse> {
+    serde_json::from_slice(body)
+        .map_err(|e| HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() })))
+}
+
+// Create an API key
+async fn admin_create_key(req: web::HttpRequest, body: web::Bytes, auth: web::Data<Authenticator>) -> HttpResponse {
+    let principal = match authenticate_admin(&req, &body, &auth).await {
+        Ok(principal) => principal,
+        Err(response) => return response,
+    };
+
+    let body: CreateApiKey = match parse_json_body(&body) {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    if let Err(error) = body.rate_limits.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": error }));
+    }
+
+    let tenant_id = body.tenant_id.unwrap_or_else(|| principal.tenant.clone());
+    if !can_manage_tenant(&principal, &tenant_id) {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "insufficient_scope",
+            "missing_scope": SCOPE_TENANTS_ADMIN,
+        }));
+    }
+    if let Err(response) = require_grantable(&principal, &body.scopes) {
+        return response;
+    }
+
+    let scopes = body.scopes.clone();
+    let (prefix, response) = match issue_api_key(&auth, &tenant_id, body.scopes, body.expires_at, body.rate_limits).await {
+        Ok(issued) => issued,
+        Err(response) => return response,
+    };
+
+    auth.audit.record(AuditEntry {
+        principal: Some(principal.subject),
+        outcome: "success".to_string(),
+        details: serde_json::json!({ "key_prefix": prefix, "tenant_id": tenant_id, "scopes": scopes }),
+        ..AuditEntry::for_request(&req, "api_key.create")
+    });
+    response
+}
+
+// List API keys
+async fn admin_list_keys(req: web::HttpRequest, auth: web::Data<Authenticator>) -> HttpResponse {
+    let principal = match authenticate_admin(&req, &[], &auth).await {
+        Ok(principal) => principal,
+        Err(response) => return response,
+    };
+
+    let keys = match auth.key_store.list().await {
+        Ok(keys) => keys,
+        Err(e) => return storage_error("Failed to list API keys", e),
+    };
+    HttpResponse::Ok().json(
+        keys.iter()
+            .filter(|record| can_manage_tenant(&principal, &record.tenant_id))
+            .map(ApiKeyRecord::to_json)
+            .collect::<Vec<_>>(),
+    )
+}
+
+// Describe an API key
+async fn admin_describe_key(req: web::HttpRequest, prefix: web::Path<String>, auth: web::Data<Authenticator>) -> HttpResponse {
+    let principal = match authenticate_admin(&req, &[], &auth).await {
+        Ok(principal) => principal,
+        Err(response) => return response,
+    };
+
+    // Keys of other tenants are reported as missing
+    match auth.key_store.find_by_prefix(&prefix).await {
+        Ok(Some(record)) if can_manage_tenant(&principal, &record.tenant_id) => HttpResponse::Ok().json(record.to_json()),
+        Ok(_) => HttpResponse::NotFound().finish(),
+        Err(e) => storage_error("Failed to look up API key", e),
+    }
+}
+
+// Rotate an API key: issue a replacement with the same scopes and limits and retire the old one
+async fn admin_rotate_key(
+    req: web::HttpRequest,
+    prefix: web::Path<String>,
+    body: web::Bytes,
+    auth: web::Data<Authenticator>,
+) -> HttpResponse {
+    let principal = match authenticate_admin(&req, &body, &auth).await {
+        Ok(principal) => principal,
+        Err(response) => return response,
+    };
+
+    // The body is optional
+    let body: RotateApiKey = if body.is_empty() {
+        RotateApiKey::default()
+    } else {
+        match parse_json_body(&body) {
+            Ok(body) => body,
+            Err(response) => return response,
+        }
+    };
+
+    let old = match auth.key_store.find_by_prefix(&prefix).await {
+        Ok(Some(record)) if !can_manage_tenant(&principal, &record.tenant_id) => return HttpResponse::NotFound().finish(),
+        Ok(Some(record)) if record.revoked_at.is_none() => record,
+        Ok(Some(_)) => return HttpResponse::Conflict().json(serde_json::json!({ "error": "api_key_revoked" })),
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(e) => return storage_error("Failed to look up API key", e),
+    };
+
+    // The replacement is handed to the caller, so it may only carry scopes the caller holds
+    if let Err(response) = require_grantable(&principal, &old.scopes) {
+        return response;
+    }
+
+    let grace_period = body.grace_period_secs;
+    if grace_period > MAX_ROTATION_GRACE_PERIOD_SECS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("grace_period_secs must be at most {}", MAX_ROTATION_GRACE_PERIOD_SECS)
+        }));
+    }
+
+    // An expired key's own expiry would issue a replacement that is already dead
+    let expires_at = body.expires_at.or(old.expires_at);
+    if expires_at.map_or(false, |expires_at| expires_at <= Utc::now()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "expires_at must be in the future; set it when rotating an expired key"
+        }));
+    }
+
+    // Retire the old key, immediately or after the grace period
+    let retired = if grace_period == 0 {
+        auth.key_store.revoke(&old.prefix).await.map(|_| ())
+    } else {
+        let retire_at = Utc::now() + chrono::Duration::seconds(grace_period as i64);
+        let expires_at = old.expires_at.map_or(retire_at, |expires_at| expires_at.min(retire_at));
+        auth.key_store.set_expiry(&old.prefix, Some(expires_at)).await
+    };
+    if let Err(e) = retired {
+        return storage_error("Failed to retire API key", e);
+    }
+    auth.auth_cache.invalidate(&old.prefix);
+
+    let (new_prefix, response) = match issue_api_key(&auth, &old.tenant_id, old.scopes, expires_at, old.rate_limits).await {
+        Ok(issued) => issued,
+        Err(response) => return response,
+    };
+
+    auth.audit.record(AuditEntry {
+        principal: Some(principal.subject),
+        outcome: "success".to_string(),
+        details: serde_json::json!({
+            "key_prefix": old.prefix,
+            "new_key_prefix": new_prefix,
+            "grace_period_secs": grace_period,
+        }),
+        ..AuditEntry::for_request(&req, "api_key.rotate")
+    });
+    response
+}
+
+// Revoke an API key
+async fn admin_revoke_key(req: web::HttpRequest, prefix: web::Path<String>, auth: web::Data<Authenticator>) -> HttpResponse {
+    let principal = match authenticate_admin(&req, &[], &auth).await {
+        Ok(principal) => principal,
+        Err(response) => return response,
+    };
+
+    // Keys of other tenants are reported as missing
+    let manageable = match auth.key_store.find_by_prefix(&prefix).await {
+        Ok(record) => record.map_or(false, |record| can_manage_tenant(&principal, &record.tenant_id)),
+        Err(e) => return storage_error("Failed to look up API key", e),
+    };
+    let revoked = if manageable {
+        match auth.key_store.revoke(&prefix).await {
+            Ok(revoked) => revoked,
+            Err(e) => return storage_error("Failed to revoke API key", e),
+        }
+    } else {
+        false
+    };
+    auth.auth_cache.invalidate(&prefix);
+
+    auth.audit.record(AuditEntry {
+        principal: Some(principal.subject),
+        outcome: if revoked { "success" } else { "not_found" }.to_string(),
+        details: serde_json::json!({ "key_prefix": prefix.as_str() }),
+        ..AuditEntry::for_request(&req, "api_key.revoke")
+    });
+    if revoked {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+// Symmetric encryption key
+#[derive(Clone)]
+struct EncryptionKey {
+    id: String,
+    bytes: [u8; 32],
+}
+
+impl EncryptionKey {
+    fn from_base64(id: &str, encoded: &str) -> Result<Self, CryptoError> {
+        let decoded = base64::decode(encoded).map_err(|_| CryptoError::InvalidKey)?;
+        if decoded.len() != 32 {
+            return Err(CryptoError::InvalidKey);
+        }
+
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&decoded);
+
+        Ok(EncryptionKey {
+            id: id.to_string(),
+            bytes,
+        })
+    }
+
+    // Generate a random 256-bit key
+    fn generate(id: &str) -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        EncryptionKey {
+            id: id.to_string(),
+            bytes,
+        }
+    }
+}
+
+// Encryption errors
+#[derive(Debug)]
+enum CryptoError {
+    InvalidKey,
+    UnknownKey(String),
+    UnsupportedAlgorithm(String),
+    KeyStore(String),
+    InvalidPath(String),
+    Encryption,
+    Decryption,
+    InvalidSignature,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::InvalidKey => write!(f, "invalid encryption key"),
+            CryptoError::UnknownKey(id) => write!(f, "unknown key-encryption key: {}", id),
+            CryptoError::UnsupportedAlgorithm(alg) => write!(f, "unsupported algorithm: {}", alg),
+            CryptoError::KeyStore(reason) => write!(f, "key store error: {}", reason),
+            CryptoError::InvalidPath(path) => write!(f, "invalid JSON path: {}", path),
+            CryptoError::Encryption => write!(f, "encryption failed"),
+            CryptoError::Decryption => write!(f, "decryption failed: ciphertext or key is invalid"),
+            CryptoError::InvalidSignature => write!(f, "signature verification failed"),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+// Encrypt plaintext under a raw 256-bit key, returning (nonce, ciphertext)
+fn seal(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
+    let cipher = Aes256Gcm::new(Key::from_slice(key));
+
+    // Generate a fresh 96-bit nonce for every message
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| CryptoError::Encryption)?;
+
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+// Decrypt and authenticate ciphertext produced by seal
+fn open(key: &[u8; 32], nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if nonce.len() != 12 {
+        return Err(CryptoError::Decryption);
+    }
+
+    let cipher = Aes256Gcm::new(Key::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| CryptoError::Decryption)
+}
+
+// Data key wrapped by a key-encryption key, stored alongside the event
+#[derive(Clone, Serialize, Deserialize)]
+struct WrappedKey {
+    // Key-encryption key id, or the recipient key id when sealed to a recipient
+    kek_id: String,
+    algorithm: String,
+    // Sender's ephemeral X25519 public key, only set when sealed to a recipient
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ephemeral_public_key: Option<Vec<u8>>,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+// Wrap a data key under the given key-encryption key
+fn wrap_with_kek(kek: &EncryptionKey, data_key: &[u8; 32]) -> Result<WrappedKey, CryptoError> {
+    // Bind the KEK id so a wrapped key can't be replayed under another KEK
+    let (nonce, ciphertext) = seal(&kek.bytes, data_key, kek.id.as_bytes())?;
+
+    Ok(WrappedKey {
+        kek_id: kek.id.clone(),
+        algorithm: ENCRYPTION_ALGORITHM.to_string(),
+        ephemeral_public_key: None,
+        nonce,
+        ciphertext,
+    })
+}
+
+// Unwrap a data key with the given key-encryption key
+fn unwrap_with_kek(kek: &EncryptionKey, wrapped: &WrappedKey) -> Result<[u8; 32], CryptoError> {
+    if wrapped.algorithm != ENCRYPTION_ALGORITHM {
+        return Err(CryptoError::UnsupportedAlgorithm(wrapped.algorithm.clone()));
+    }
+
+    let plaintext = open(&kek.bytes, &wrapped.nonce, &wrapped.ciphertext, kek.id.as_bytes())?;
+    if plaintext.len() != 32 {
+        return Err(CryptoError::Decryption);
+    }
+
+    let mut data_key = [0u8; 32];
+    data_key.copy_from_slice(&plaintext);
+    Ok(data_key)
+}
+
+// Algorithm identifier for data keys sealed to a recipient's X25519 public key
+const RECIPIENT_SEAL_ALGORITHM: &str = "X25519-HKDF-SHA256+AES-256-GCM";
+
+// Derive the key that seals a data key from an X25519 shared secret
+fn derive_recipient_wrapping_key(shared_secret: &[u8; 32], ephemeral_public: &[u8; 32], recipient_public: &[u8; 32]) -> [u8; 32] {
+    // Salt with both public keys so the derived key is bound to this exchange
+    let mut salt = Vec::with_capacity(64);
+    salt.extend_from_slice(ephemeral_public);
+    salt.extend_from_slice(recipient_public);
+
+    let mut wrapping_key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(b"secure-notifier recipient data key", &mut wrapping_key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    wrapping_key
+}
+
+// Seal a data key to a recipient's public key, so only the recipient can unwrap it
+fn seal_to_recipient(recipient_key: &RecipientKey, data_key: &[u8; 32]) -> Result<WrappedKey, CryptoError> {
+    let recipient_public = PublicKey::from(recipient_key.public_key);
+
+    // Fresh ephemeral key pair for every event
+    let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral_secret);
+
+    // Reject low-order public keys, which would yield a predictable shared secret
+    let shared_secret = ephemeral_secret.diffie_hellman(&recipient_public);
+    if !shared_secret.was_contributory() {
+        return Err(CryptoError::InvalidKey);
+    }
+
+    let wrapping_key = derive_recipient_wrapping_key(
+        shared_secret.as_bytes(),
+        ephemeral_public.as_bytes(),
+        &recipient_key.public_key,
+    );
+    let kek_id = recipient_key.id.to_string();
+    let (nonce, ciphertext) = seal(&wrapping_key, data_key, kek_id.as_bytes())?;
+
+    Ok(WrappedKey {
+        kek_id,
+        algorithm: RECIPIENT_SEAL_ALGORITHM.to_string(),
+        ephemeral_public_key: Some(ephemeral_public.as_bytes().to_vec()),
+        nonce,
+        ciphertext,
+    })
+}
+
+// Whether data keys can be sealed to `public_key`: low-order points give
+// every sender the same predictable shared secret
+fn is_usable_recipient_key(public_key: &[u8; 32]) -> bool {
+    EphemeralSecret::random_from_rng(OsRng)
+        .diffie_hellman(&PublicKey::from(*public_key))
+        .was_contributory()
+}
+
+// Recover a data key sealed to a recipient, using the recipient's private key
+fn unseal_for_recipient(recipient_secret: &StaticSecret, wrapped: &WrappedKey) -> Result<[u8; 32], CryptoError> {
+    if wrapped.algorithm != RECIPIENT_SEAL_ALGORITHM {
+        return Err(CryptoError::UnsupportedAlgorithm(wrapped.algorithm.clone()));
+    }
+
+    let ephemeral_bytes: [u8; 32] = wrapped
+        .ephemeral_public_key
+        .as_deref()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(CryptoError::Decryption)?;
+    let ephemeral_public = PublicKey::from(ephemeral_bytes);
+    let recipient_public = PublicKey::from(recipient_secret);
+
+    let shared_secret = recipient_secret.diffie_hellman(&ephemeral_public);
+    let wrapping_key = derive_recipient_wrapping_key(
+        shared_secret.as_bytes(),
+        &ephemeral_bytes,
+        recipient_public.as_bytes(),
+    );
+
+    let plaintext = open(&wrapping_key, &wrapped.nonce, &wrapped.ciphertext, wrapped.kek_id.as_bytes())?;
+    plaintext.try_into().map_err(|_| CryptoError::Decryption)
+}
+
+// Key Provider: holds key-encryption keys and wraps per-event data keys.
+// Only the active key wraps new data keys; every other key is decrypt-only.
+trait KeyProvider: Send + Sync {
+    // Id of the key-encryption key used to wrap new data keys
+    fn active_key_id(&self) -> String;
+
+    // Ids of every key-encryption key this provider holds
+    fn key_ids(&self) -> Vec<String>;
+
+    // Wrap a data key under the active key-encryption key
+    fn wrap_key(&self, data_key: &[u8; 32]) -> Result<WrappedKey, CryptoError>;
+
+    // Unwrap a data key with whichever key-encryption key wrapped it
+    fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<[u8; 32], CryptoError>;
+
+    // Add a new key-encryption key and make it the active one
+    fn rotate_key(&self, new_key: EncryptionKey) -> Result<(), CryptoError>;
+}
+
+// Active key id plus every known key-encryption key
+struct KeyRing {
+    active_key_id: String,
+    keys: HashMap<String, EncryptionKey>,
+}
+
+// In-memory Key Provider, used in tests and ephemeral environments
+struct InMemoryKeyProvider {
+    ring: RwLock<KeyRing>,
+}
+
+impl InMemoryKeyProvider {
+    fn new(active_key: EncryptionKey) -> Self {
+        let active_key_id = active_key.id.clone();
+        let mut keys = HashMap::new();
+        keys.insert(active_key_id.clone(), active_key);
+
+        InMemoryKeyProvider {
+            ring: RwLock::new(KeyRing { active_key_id, keys }),
+        }
+    }
+
+    // Generate a provider with a single random key-encryption key
+    fn generate(key_id: &str) -> Self {
+        InMemoryKeyProvider::new(EncryptionKey::generate(key_id))
+    }
+
+    fn add_key(&self, key: EncryptionKey) {
+        let mut ring = self.ring.write().expect("Key ring lock poisoned");
+        ring.keys.insert(key.id.clone(), key);
+    }
+
+    // Snapshot the key ring in key file format
+    fn to_key_file(&self) -> KeyFile {
+        let ring = self.ring.read().expect("Key ring lock poisoned");
+
+        KeyFile {
+            active_key_id: ring.active_key_id.clone(),
+            keys: ring
+                .keys
+                .values()
+                .map(|key| (key.id.clone(), base64::encode(key.bytes)))
+                .collect(),
+        }
+    }
+}
+
+impl KeyProvider for InMemoryKeyProvider {
+    fn active_key_id(&self) -> String {
+        self.ring.read().expect("Key ring lock poisoned").active_key_id.clone()
+    }
+
+    fn key_ids(&self) -> Vec<String> {
+        let ring = self.ring.read().expect("Key ring lock poisoned");
+        let mut ids: Vec<String> = ring.keys.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    fn wrap_key(&self, data_key: &[u8; 32]) -> Result<WrappedKey, CryptoError> {
+        let ring = self.ring.read().expect("Key ring lock poisoned");
+        let kek = ring
+            .keys
+            .get(&ring.active_key_id)
+            .ok_or_else(|| CryptoError::UnknownKey(ring.active_key_id.clone()))?;
+
+        wrap_with_kek(kek, data_key)
+    }
+
+    fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<[u8; 32], CryptoError> {
+        let ring = self.ring.read().expect("Key ring lock poisoned");
+        let kek = ring
+            .keys
+            .get(&wrapped.kek_id)
+            .ok_or_else(|| CryptoError::UnknownKey(wrapped.kek_id.clone()))?;
+
+        unwrap_with_kek(kek, wrapped)
+    }
+
+    fn rotate_key(&self, new_key: EncryptionKey) -> Result<(), CryptoError> {
+        let mut ring = self.ring.write().expect("Key ring lock poisoned");
+        if ring.keys.contains_key(&new_key.id) {
+            return Err(CryptoError::KeyStore(format!("key {} already exists", new_key.id)));
+        }
+
+        ring.active_key_id = new_key.id.clone();
+        ring.keys.insert(new_key.id.clone(), new_key);
+        Ok(())
+    }
+}
+
+// On-disk key file format for LocalKeyProvider
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    active_key_id: String,
+    // Key id -> base64-encoded 256-bit key
+    keys: HashMap<String, String>,
+}
+
+impl KeyFile {
+    fn read(path: &Path) -> Result<Self, CryptoError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| CryptoError::KeyStore(format!("{}: {}", path.display(), e)))?;
+
+        serde_json::from_str(&contents).map_err(|e| CryptoError::KeyStore(format!("{}: {}", path.display(), e)))
+    }
+
+    // Write via a temporary file and rename so readers never see a partial file
+    fn write(&self, path: &Path) -> Result<(), CryptoError> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| CryptoError::KeyStore(e.to_string()))?;
+        let tmp_path = path.with_extension("tmp");
+
+        std::fs::write(&tmp_path, contents)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .map_err(|e| CryptoError::KeyStore(format!("{}: {}", path.display(), e)))
+    }
+
+    fn into_provider(self) -> Result<InMemoryKeyProvider, CryptoError> {
+        // Decode every key and check the active one is present
+        let active = self
+            .keys
+            .get(&self.active_key_id)
+            .ok_or_else(|| CryptoError::UnknownKey(self.active_key_id.clone()))?;
+        let provider = InMemoryKeyProvider::new(EncryptionKey::from_base64(&self.active_key_id, active)?);
+        for (id, encoded) in &self.keys {
+            provider.add_key(EncryptionKey::from_base64(id, encoded)?);
+        }
+
+        Ok(provider)
+    }
+}
+
+// File-backed Key Provider, reading key-encryption keys from a local JSON file
+struct LocalKeyProvider {
+    path: PathBuf,
+    inner: RwLock<InMemoryKeyProvider>,
+}
+
+impl LocalKeyProvider {
+    fn load(path: impl Into<PathBuf>) -> Result<Self, CryptoError> {
+        let path = path.into();
+        let inner = KeyFile::read(&path)?.into_provider()?;
+
+        Ok(LocalKeyProvider {
+            path,
+            inner: RwLock::new(inner),
+        })
+    }
+
+    // Re-read the key file, picking up rotations made by another process
+    fn reload(&self) -> Result<(), CryptoError> {
+        let reloaded = KeyFile::read(&self.path)?.into_provider()?;
+        *self.inner.write().expect("Key provider lock poisoned") = reloaded;
+        Ok(())
+    }
+}
+
+impl KeyProvider for LocalKeyProvider {
+    fn active_key_id(&self) -> String {
+        self.inner.read().expect("Key provider lock poisoned").active_key_id()
+    }
+
+    fn key_ids(&self) -> Vec<String> {
+        self.inner.read().expect("Key provider lock poisoned").key_ids()
+    }
+
+    fn wrap_key(&self, data_key: &[u8; 32]) -> Result<WrappedKey, CryptoError> {
+        self.inner.read().expect("Key provider lock poisoned").wrap_key(data_key)
+    }
+
+    fn unwrap_key(&self, wrapped: &WrappedKey) -> Result<[u8; 32], CryptoError> {
+        self.inner.read().expect("Key provider lock poisoned").unwrap_key(wrapped)
+    }
+
+    fn rotate_key(&self, new_key: EncryptionKey) -> Result<(), CryptoError> {
+        let inner = self.inner.write().expect("Key provider lock poisoned");
+
+        // Persist the new key before activating it, so a crash can't lose it
+        let mut file = inner.to_key_file();
+        if file.keys.contains_key(&new_key.id) {
+            return Err(CryptoError::KeyStore(format!("key {} already exists", new_key.id)));
+        }
+        file.active_key_id = new_key.id.clone();
+        file.keys.insert(new_key.id.clone(), base64::encode(new_key.bytes));
+        file.write(&self.path)?;
+
+        inner.rotate_key(new_key)
+    }
+}
+
+// JSON path segment: `.key` / `['key']`, `[index]` or `[*]`. In `['key']`,
+// a backslash escapes the next character, so keys may contain `'` and `]`.
+#[derive(Clone, Debug, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+// JSON path such as `$.user.email` or `$.items[*].sku`; `$` is the whole document
+#[derive(Clone, Debug, PartialEq)]
+struct JsonPath(Vec<PathSegment>);
+
+impl JsonPath {
+    fn parse(path: &str) -> Result<Self, CryptoError> {
+        let invalid = || CryptoError::InvalidPath(path.to_string());
+        let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+        let mut segments = Vec::new();
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(|c| c == '.' || c == '[').unwrap_or(after.len());
+                if end == 0 {
+                    return Err(invalid());
+                }
+                segments.push(PathSegment::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix("['") {
+                let mut key = String::new();
+                let mut chars = after.char_indices();
+                let end = loop {
+                    match chars.next().ok_or_else(invalid)? {
+                        (_, '\\') => key.push(chars.next().ok_or_else(invalid)?.1),
+                        (end, '\'') => break end,
+                        (_, c) => key.push(c),
+                    }
+                };
+                segments.push(PathSegment::Key(key));
+                rest = after[end + 1..].strip_prefix(']').ok_or_else(invalid)?;
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(invalid)?;
+                let inner = &after[..end];
+                let segment = if inner == "*" {
+                    PathSegment::Wildcard
+                } else {
+                    PathSegment::Index(inner.parse().map_err(|_| invalid())?)
+                };
+                segments.push(segment);
+                rest = &after[end + 1..];
+            } else {
+                return Err(invalid());
+            }
+        }
+
+        Ok(JsonPath(segments))
+    }
+
+    // Resolve wildcards into the concrete paths present in `value`
+    fn expand(&self, value: &Value) -> Vec<JsonPath> {
+        let mut matches = Vec::new();
+        expand_json_path(value, &self.0, &mut Vec::new(), &mut matches);
+        matches
+    }
+
+    // Borrow the value at a concrete path
+    fn get_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        self.0.iter().try_fold(value, |current, segment| match segment {
+            PathSegment::Key(key) => current.get_mut(key.as_str()),
+            PathSegment::Index(index) => current.get_mut(*index),
+            PathSegment::Wildcard => None,
+        })
+    }
+}
+
+fn expand_json_path(value: &Value, remaining: &[PathSegment], prefix: &mut Vec<PathSegment>, matches: &mut Vec<JsonPath>) {
+    let (segment, rest) = match remaining.split_first() {
+        Some(split) => split,
+        None => {
+            matches.push(JsonPath(prefix.clone()));
+            return;
+        }
+    };
+
+    let mut descend = |child: &Value, concrete: PathSegment, prefix: &mut Vec<PathSegment>| {
+        prefix.push(concrete);
+        expand_json_path(child, rest, prefix, matches);
+        prefix.pop();
+    };
+
+    match (segment, value) {
+        (PathSegment::Key(key), Value::Object(map)) => {
+            if let Some(child) = map.get(key) {
+                descend(child, segment.clone(), prefix);
+            }
+        }
+        (PathSegment::Index(index), Value::Array(items)) => {
+            if let Some(child) = items.get(*index) {
+                descend(child, segment.clone(), prefix);
+            }
+        }
+        (PathSegment::Wildcard, Value::Array(items)) => {
+            for (index, child) in items.iter().enumerate() {
+                descend(child, PathSegment::Index(index), prefix);
+            }
+        }
+        (PathSegment::Wildcard, Value::Object(map)) => {
+            for (key, child) in map {
+                descend(child, PathSegment::Key(key.clone()), prefix);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$")?;
+        for segment in &self.0 {
+            match segment {
+                PathSegment::Key(key) if !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                    write!(f, ".{}", key)?
+                }
+                PathSegment::Key(key) => {
+                    write!(f, "['")?;
+                    for c in key.chars() {
+                        if matches!(c, '\\' | '\'' | ']') {
+                            write!(f, "\\")?;
+                        }
+                        write!(f, "{}", c)?;
+                    }
+                    write!(f, "']")?
+                }
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+                PathSegment::Wildcard => write!(f, "[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Field-level encryption policy: the JSON paths encrypted for each event type,
+// and for event types without one, Config.default_field_encryption
+struct FieldEncryptionPolicies {
+    by_event_type: HashMap<String, Vec<JsonPath>>,
+    default: Vec<JsonPath>,
+}
+
+impl FieldEncryptionPolicies {
+    fn from_config(policies: &HashMap<String, Vec<String>>, default: &[String]) -> Result<Self, CryptoError> {
+        let parse = |paths: &[String]| paths.iter().map(|path| JsonPath::parse(path)).collect::<Result<Vec<_>, _>>();
+
+        let mut by_event_type = HashMap::new();
+        for (event_type, paths) in policies {
+            by_event_type.insert(event_type.clone(), parse(paths)?);
+        }
+
+        Ok(FieldEncryptionPolicies {
+            by_event_type,
+            default: parse(default)?,
+        })
+    }
+
+    fn paths_for(&self, event_type: &str) -> &[JsonPath] {
+        self.by_event_type.get(event_type).unwrap_or(&self.default)
+    }
+}
+
+// Copy of an event with every field named by the policy replaced by null,
+// as in its EncryptedEvent
+fn redact_event(event: &Event, policy: &[JsonPath]) -> Event {
+    let mut redacted = event.clone();
+    for pattern in policy {
+        for path in pattern.expand(&event.data) {
+            if let Some(field) = path.get_mut(&mut redacted.data) {
+                *field = Value::Null;
+            }
+        }
+    }
+    redacted
+}
+
+// Associated data for an encrypted field: binds it to its event and location
+fn field_aad(event_id: Uuid, path: &str) -> String {
+    format!("{}:{}", event_id, path)
+}
+
+// Encryption Service
+async fn encrypt_event(
+    event: Event,
+    tenant_id: &str,
+    keys: &dyn KeyProvider,
+    policy: &[JsonPath],
+    recipient_key: Option<&RecipientKey>,
+) -> Result<EncryptedEvent, CryptoError> {
+    // Generate a fresh data key for this event
+    let mut data_key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut data_key);
+
+    // Encrypt each field named by the policy, leaving a null in its place
+    let mut data = event.data;
+    let mut encrypted_fields = Vec::new();
+    for pattern in policy {
+        for path in pattern.expand(&data) {
+            let field = path.get_mut(&mut data).expect("Expanded path must exist");
+            let plaintext = serde_json::to_vec(&field.take()).expect("Failed to serialize event field");
+
+            let path = path.to_string();
+            let (nonce, ciphertext) = seal(&data_key, &plaintext, field_aad(event.id, &path).as_bytes())?;
+
+            encrypted_fields.push(EncryptedField { path, nonce, ciphertext });
+        }
+    }
+
+    // Seal the data key to the recipient if they registered a key, so only
+    // they can read it; otherwise wrap it under the active key-encryption key
+    let wrapped_key = match recipient_key {
+        Some(recipient_key) => seal_to_recipient(recipient_key, &data_key),
+        None => keys.wrap_key(&data_key),
+    }?;
+
+    Ok(EncryptedEvent {
+        id: event.id,
+        tenant_id: tenant_id.to_string(),
+        event_type: event.event_type,
+        recipient: event.recipient,
+        algorithm: ENCRYPTION_ALGORITHM.to_string(),
+        wrapped_key,
+        data,
+        encrypted_fields,
+    })
+}
+
+// Decryption Service
+async fn decrypt_event(event: &EncryptedEvent, keys: &dyn KeyProvider) -> Result<Event, CryptoError> {
+    // Refuse anything we did not produce
+    if event.algorithm != ENCRYPTION_ALGORITHM {
+        return Err(CryptoError::UnsupportedAlgorithm(event.algorithm.clone()));
+    }
+
+    // Recover the data key
+    let data_key = keys.unwrap_key(&event.wrapped_key)?;
+    decrypt_event_fields(event, &data_key)
+}
+
+// Decrypt an event sealed to a recipient, using the recipient's private key
+async fn decrypt_event_for_recipient(event: &EncryptedEvent, recipient_secret: &StaticSecret) -> Result<Event, CryptoError> {
+    if event.algorithm != ENCRYPTION_ALGORITHM {
+        return Err(CryptoError::UnsupportedAlgorithm(event.algorithm.clone()));
+    }
+
+    let data_key = unseal_for_recipient(recipient_secret, &event.wrapped_key)?;
+    decrypt_event_fields(event, &data_key)
+}
+
+// Decrypt every encrypted field of an event with its data key
+fn decrypt_event_fields(event: &EncryptedEvent, data_key: &[u8; 32]) -> Result<Event, CryptoError> {
+    // Decrypt fields in reverse so nested paths are restored before their parents
+    let mut data = event.data.clone();
+    for field in event.encrypted_fields.iter().rev() {
+        let plaintext = open(data_key, &field.nonce, &field.ciphertext, field_aad(event.id, &field.path).as_bytes())?;
+        let value: Value = serde_json::from_slice(&plaintext).map_err(|_| CryptoError::Decryption)?;
+
+        let slot = JsonPath::parse(&field.path)?
+            .get_mut(&mut data)
+            .ok_or_else(|| CryptoError::InvalidPath(field.path.clone()))?;
+        *slot = value;
+    }
+
+    Ok(Event {
+        id: event.id,
+        event_type: event.event_type.clone(),
+        recipient: event.recipient.clone(),
+        data,
+    })
+}
+
+// Number of events re-encrypted per batch and per checkpoint
+const REENCRYPTION_BATCH_SIZE: i64 = 500;
+
+// How often the server re-reads the key file to pick up rotations
+const KEY_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+// Re-encryption progress, published while a rotation job runs
+#[derive(Clone, Debug, Default)]
+struct ReencryptionProgress {
+    job_id: Uuid,
+    target_key_id: String,
+    total: i64,
+    processed: i64,
+    failed: i64,
+    done: bool,
+}
+
+// Which tenants' events a re-encryption job covers
+#[derive(Clone, Debug, Default)]
+struct TenantFilter {
+    // A tenant with its own key file
+    only: Option<String>,
+    // Tenants with their own key files, skipped when re-encrypting the shared keys
+    excluded: Vec<String>,
+}
+
+// Tenant Keys: each tenant's key-encryption keys. Tenants without a key file
+// of their own share the one named by Config.key_file.
+struct TenantKeys {
+    shared: Arc<LocalKeyProvider>,
+    tenants: HashMap<String, Arc<LocalKeyProvider>>,
+}
+
+impl TenantKeys {
+    fn load(config: &Config) -> Result<Self, CryptoError> {
+        let shared = Arc::new(LocalKeyProvider::load(&config.key_file)?);
+
+        let mut tenants = HashMap::new();
+        for (tenant_id, tenant) in &config.tenants {
+            if let Some(key_file) = &tenant.key_file {
+                tenants.insert(tenant_id.clone(), Arc::new(LocalKeyProvider::load(key_file)?));
+            }
+        }
+
+        Ok(TenantKeys { shared, tenants })
+    }
+
+    fn for_tenant(&self, tenant_id: &str) -> Arc<dyn KeyProvider> {
+        match self.tenants.get(tenant_id) {
+            Some(keys) => keys.clone(),
+            None => self.shared.clone(),
+        }
+    }
+
+    // Every provider, with the tenants whose events it wraps
+    fn providers(&self) -> Vec<(Arc<dyn KeyProvider>, TenantFilter)> {
+        let shared: Arc<dyn KeyProvider> = self.shared.clone();
+        let mut providers = vec![(
+            shared,
+            TenantFilter {
+                only: None,
+                excluded: self.tenants.keys().cloned().collect(),
+            },
+        )];
+
+        for (tenant_id, keys) in &self.tenants {
+            let keys: Arc<dyn KeyProvider> = keys.clone();
+            providers.push((
+                keys,
+                TenantFilter {
+                    only: Some(tenant_id.clone()),
+                    excluded: Vec::new(),
+                },
+            ));
+        }
+        providers
+    }
+
+    // Re-read every key file, picking up rotations made by another process
+    fn reload(&self) -> Result<(), CryptoError> {
+        self.shared.reload()?;
+        for keys in self.tenants.values() {
+            keys.reload()?;
+        }
+        Ok(())
+    }
+}
+
+// Key Rotation: activate a new key-encryption key and re-encrypt stored events to it
+fn rotate_encryption_key(
+    keys: Arc<dyn KeyProvider>,
+    tenants: TenantFilter,
+    pool: PgPool,
+    new_key_id: &str,
+) -> Result<watch::Receiver<ReencryptionProgress>, CryptoError> {
+    keys.rotate_key(EncryptionKey::generate(new_key_id))?;
+    Ok(start_reencryption(keys, tenants, pool))
+}
+
+// Start, or resume, re-encryption to the active key in the background
+fn start_reencryption(
+    keys: Arc<dyn KeyProvider>,
+    tenants: TenantFilter,
+    pool: PgPool,
+) -> watch::Receiver<ReencryptionProgress> {
+    let (progress_tx, progress_rx) = watch::channel(ReencryptionProgress::default());
+
+    tokio::spawn(async move {
+        if let Err(e) = run_reencryption(keys.as_ref(), &tenants, &pool, &progress_tx).await {
+            eprintln!("Re-encryption job failed: {}", e);
+        }
+    });
+
+    progress_rx
+}
+
+// Re-encryption job: re-wrap every stored data key of `tenants` under the
+// active key. Event ciphertext is untouched; only the wrapped data key changes.
+async fn run_reencryption(
+    keys: &dyn KeyProvider,
+    tenants: &TenantFilter,
+    pool: &PgPool,
+    progress_tx: &watch::Sender<ReencryptionProgress>,
+) -> Result<(), sqlx::Error> {
+    let target_key_id = keys.active_key_id();
+
+    // Count events still wrapped under an older key
+    let remaining: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM encrypted_events
+         WHERE kek_id <> $1 AND ($2::text IS NULL OR tenant_id = $2) AND tenant_id <> ALL($3)",
+    )
+    .bind(&target_key_id)
+    .bind(&tenants.only)
+    .bind(&tenants.excluded)
+    .fetch_one(pool)
+    .await?;
+
+    // Resume an unfinished job for this key, or start a new one
+    let existing = sqlx::query(
+        "SELECT id, last_event_id, processed, failed FROM key_rotation_jobs
+         WHERE target_key_id = $1 AND tenant_id IS NOT DISTINCT FROM $2 AND finished_at IS NULL",
+    )
+    .bind(&target_key_id)
+    .bind(&tenants.only)
+    .fetch_optional(pool)
+    .await?;
+
+    let (job_id, mut cursor, mut processed, mut failed) = match existing {
+        Some(row) => (
+            row.get::<Uuid, _>("id"),
+            row.get::<Option<Uuid>, _>("last_event_id"),
+            row.get::<i64, _>("processed"),
+            row.get::<i64, _>("failed"),
+        ),
+        None if remaining == 0 => {
+            let _ = progress_tx.send(ReencryptionProgress {
+                target_key_id,
+                done: true,
+                ..ReencryptionProgress::default()
+            });
+            return Ok(());
+        }
+        None => {
+            // Jobs targeting an older key of the same provider are superseded by this one
+            sqlx::query(
+                "UPDATE key_rotation_jobs SET finished_at = now()
+                 WHERE finished_at IS NULL AND tenant_id IS NOT DISTINCT FROM $1",
+            )
+            .bind(&tenants.only)
+            .execute(pool)
+            .await?;
+
+            let job_id = Uuid::new_v4();
+            sqlx::query("INSERT INTO key_rotation_jobs (id, target_key_id, tenant_id) VALUES ($1, $2, $3)")
+                .bind(job_id)
+                .bind(&target_key_id)
+                .bind(&tenants.only)
+                .execute(pool)
+                .await?;
+
+            (job_id, None, 0, 0)
+        }
+    };
+
+    let report = |processed: i64, failed: i64, done: bool| {
+        let _ = progress_tx.send(ReencryptionProgress {
+            job_id,
+            target_key_id: target_key_id.clone(),
+            total: processed + remaining,
+            processed,
+            failed,
+            done,
+        });
+    };
+    report(processed, failed, false);
+
+    // Events that failed in this run, so later passes don't count them twice
+    let mut failed_ids = HashSet::new();
+
+    loop {
+        // Fetch the next batch after the checkpoint
+        let rows = sqlx::query(
+            "SELECT id, tenant_id, wrapped_key FROM encrypted_events
+             WHERE kek_id <> $1 AND ($2::uuid IS NULL OR id > $2)
+               AND ($4::text IS NULL OR tenant_id = $4) AND tenant_id <> ALL($5)
+             ORDER BY id, tenant_id LIMIT $3",
+        )
+        .bind(&target_key_id)
+        .bind(cursor)
+        .bind(REENCRYPTION_BATCH_SIZE)
+        .bind(&tenants.only)
+        .bind(&tenants.excluded)
+        .fetch_all(pool)
+        .await?;
+
+        if rows.is_empty() {
+            // Event ids are random, so events written under the old key until
+            // every process reloads the key file may sit behind the cursor
+            let missed: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM encrypted_events
+                 WHERE kek_id <> $1 AND ($2::text IS NULL OR tenant_id = $2) AND tenant_id <> ALL($3)
+                   AND (tenant_id || '/' || id::text) <> ALL($4)",
+            )
+            .bind(&target_key_id)
+            .bind(&tenants.only)
+            .bind(&tenants.excluded)
+            .bind(failed_ids.iter().map(|(tenant_id, id)| format!("{}/{}", tenant_id, id)).collect::<Vec<_>>())
+            .fetch_one(pool)
+            .await?;
+
+            if missed == 0 || cursor.is_none() {
+                break;
+            }
+            cursor = None;
+            continue;
+        }
+
+        for row in rows {
+            let id: Uuid = row.get("id");
+            let tenant_id: String = row.get("tenant_id");
+            let Json(wrapped): Json<WrappedKey> = row.get("wrapped_key");
+            if failed_ids.contains(&(tenant_id.clone(), id)) {
+                cursor = Some(id);
+                continue;
+            }
+
+            match keys.unwrap_key(&wrapped).and_then(|data_key| keys.wrap_key(&data_key)) {
+                Ok(rewrapped) => {
+                    // Guard on the old key id so concurrent writers are never overwritten
+                    sqlx::query(
+                        "UPDATE encrypted_events SET wrapped_key = $1, kek_id = $2
+                         WHERE tenant_id = $3 AND id = $4 AND kek_id = $5",
+                    )
+                    .bind(Json(&rewrapped))
+                    .bind(&rewrapped.kek_id)
+                    .bind(&tenant_id)
+                    .bind(id)
+                    .bind(&wrapped.kek_id)
+                    .execute(pool)
+                    .await?;
+
+                    processed += 1;
+                }
+                Err(e) => {
+                    eprintln!("Failed to re-encrypt event {}: {}", id, e);
+                    failed_ids.insert((tenant_id, id));
+                    failed += 1;
+                }
+            }
+
+            cursor = Some(id);
+        }
+
+        // Checkpoint so an interrupted job resumes after the last batch
+        sqlx::query(
+            "UPDATE key_rotation_jobs SET last_event_id = $1, processed = $2, failed = $3, updated_at = now()
+             WHERE id = $4",
+        )
+        .bind(cursor)
+        .bind(processed)
+        .bind(failed)
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+
+        report(processed, failed, false);
+    }
+
+    sqlx::query("UPDATE key_rotation_jobs SET finished_at = now(), updated_at = now() WHERE id = $1")
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+
+    report(processed, failed, true);
+    Ok(())
+}
+
+// Event Store: where accepted events are kept, and the recipient keys used to seal them
+#[async_trait]
+trait EventStore: Send + Sync {
+    async fn store(&self, event: &EncryptedEvent) -> Result<(), sqlx::Error>;
+
+    // Event type and acceptance time of one of a tenant's events
+    async fn accepted(&self, tenant_id: &str, event_id: Uuid) -> Result<Option<(String, DateTime<Utc>)>, sqlx::Error>;
+
+    async fn active_recipient_key(&self, tenant_id: &str, recipient_id: &str) -> Result<Option<RecipientKey>, sqlx::Error>;
+}
+
+// Postgres Event Store: events and recipient keys in the shared database
+struct PostgresEventStore {
+    pool: PgPool,
+}
+
+#[async_trait]
+impl EventStore for PostgresEventStore {
+    async fn store(&self, event: &EncryptedEvent) -> Result<(), sqlx::Error> {
+        store_encrypted_event(&self.pool, event).await
+    }
+
+    async fn accepted(&self, tenant_id: &str, event_id: Uuid) -> Result<Option<(String, DateTime<Utc>)>, sqlx::Error> {
+        let row = sqlx::query("SELECT event_type, created_at FROM encrypted_events WHERE id = $1 AND tenant_id = $2")
+            .bind(event_id)
+            .bind(tenant_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| (row.get("event_type"), row.get("created_at"))))
+    }
+
+    async fn active_recipient_key(&self, tenant_id: &str, recipient_id: &str) -> Result<Option<RecipientKey>, sqlx::Error> {
+        active_recipient_key(&self.pool, tenant_id, recipient_id).await
+    }
+}
+
+// Discarding Event Store, used when Postgres is unavailable: events are still
+// delivered but not kept, and with no registry nothing is sealed to recipients
+struct DiscardingEventStore;
+
+#[async_trait]
+impl EventStore for DiscardingEventStore {
+    async fn store(&self, _event: &EncryptedEvent) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    async fn accepted(&self, _tenant_id: &str, _event_id: Uuid) -> Result<Option<(String, DateTime<Utc>)>, sqlx::Error> {
+        Ok(None)
+    }
+
+    async fn active_recipient_key(&self, _tenant_id: &str, _recipient_id: &str) -> Result<Option<RecipientKey>, sqlx::Error> {
+        Ok(None)
+    }
+}
+
+// Persist an encrypted event so it can be re-encrypted on key rotation.
+// Events sealed to a recipient are stored with a NULL kek_id and skipped.
+async fn store_encrypted_event(pool: &PgPool, event: &EncryptedEvent) -> Result<(), sqlx::Error> {
+    let kek_id = match event.wrapped_key.ephemeral_public_key {
+        Some(_) => None,
+        None => Some(&event.wrapped_key.kek_id),
+    };
+
+    sqlx::query(
+        "INSERT INTO encrypted_events (id, tenant_id, event_type, recipient_id, algorithm, kek_id, wrapped_key, data, encrypted_fields)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(event.id)
+    .bind(&event.tenant_id)
+    .bind(&event.event_type)
+    .bind(&event.recipient)
+    .bind(&event.algorithm)
+    .bind(kek_id)
+    .bind(Json(&event.wrapped_key))
+    .bind(Json(&event.data))
+    .bind(Json(&event.encrypted_fields))
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Recipient public key, registered so notifications can be sealed to the recipient
+struct RecipientKey {
+    id: Uuid,
+    tenant_id: String,
+    recipient_id: String,
+    public_key: [u8; 32],
+    created_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl RecipientKey {
+    fn from_row(row: &PgRow) -> Self {
+        let public_key: Vec<u8> = row.get("public_key");
+
+        RecipientKey {
+            id: row.get("id"),
+            tenant_id: row.get("tenant_id"),
+            recipient_id: row.get("recipient_id"),
+            public_key: public_key.try_into().expect("Stored recipient key must be 32 bytes"),
+            created_at: row.get("created_at"),
+            revoked_at: row.get("revoked_at"),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "id": self.id,
+            "tenant_id": self.tenant_id,
+            "recipient_id": self.recipient_id,
+            "public_key": base64::encode(self.public_key),
+            "created_at": self.created_at,
+            "revoked_at": self.revoked_at,
+        })
+    }
+}
+
+// Recipient Key Registry: the most recently registered, unrevoked key wins.
+// Recipient ids are scoped to a tenant.
+async fn active_recipient_key(pool: &PgPool, tenant_id: &str, recipient_id: &str) -> Result<Option<RecipientKey>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT id, tenant_id, recipient_id, public_key, created_at, revoked_at FROM recipient_keys
+         WHERE tenant_id = $1 AND recipient_id = $2 AND revoked_at IS NULL
+         ORDER BY created_at DESC LIMIT 1",
+    )
+    .bind(tenant_id)
+    .bind(recipient_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.as_ref().map(RecipientKey::from_row))
+}
+
+async fn list_recipient_keys(pool: &PgPool, tenant_id: &str, recipient_id: &str) -> Result<Vec<RecipientKey>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, tenant_id, recipient_id, public_key, created_at, revoked_at FROM recipient_keys
+         WHERE tenant_id = $1 AND recipient_id = $2 ORDER BY created_at DESC",
+    )
+    .bind(tenant_id)
+    .bind(recipient_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(RecipientKey::from_row).collect())
+}
+
+async fn register_recipient_key(
+    pool: &PgPool,
+    tenant_id: &str,
+    recipient_id: &str,
+    public_key: [u8; 32],
+) -> Result<RecipientKey, sqlx::Error> {
+    let row = sqlx::query(
+        "INSERT INTO recipient_keys (id, tenant_id, recipient_id, public_key) VALUES ($1, $2, $3, $4)
+         RETURNING id, tenant_id, recipient_id, public_key, created_at, revoked_at",
+    )
+    .bind(Uuid::new_v4())
+    .bind(tenant_id)
+    .bind(recipient_id)
+    .bind(&public_key[..])
+    .fetch_one(pool)
+    .await?;
+
+    Ok(RecipientKey::from_row(&row))
+}
+
+// Returns false when no such unrevoked key exists
+async fn revoke_recipient_key(pool: &PgPool, tenant_id: &str, recipient_id: &str, key_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE recipient_keys SET revoked_at = now()
+         WHERE id = $1 AND tenant_id = $2 AND recipient_id = $3 AND revoked_at IS NULL",
+    )
+    .bind(key_id)
+    .bind(tenant_id)
+    .bind(recipient_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Request body for uploading a recipient key
+#[derive(Deserialize)]
+struct UploadRecipientKey {
+    // Base64-encoded 32-byte X25519 public key
+    public_key: String,
+}
+
+// Upload a recipient public key
+async fn upload_recipient_key(
+    req: web::HttpRequest,
+    recipient_id: web::Path<String>,
+    body: web::Bytes,
+    db_pool: web::Data<Option<PgPool>>,
+    auth: web::Data<Authenticator>,
+) -> HttpResponse {
+    let principal = match authenticate_request(&req, &body, &auth).await {
+        Ok(principal) => principal,
+        Err(e) => return e.response(),
+    };
+    if let Err(response) = require_scope(&principal, SCOPE_RECIPIENT_KEYS) {
+        return response;
+    }
+    // Recipient keys and webhooks live only in Postgres
+    let db_pool = match db_pool.get_ref() {
+        Some(db_pool) => db_pool,
+        None => return storage_disabled(),
+    };
+
+    let body: UploadRecipientKey = match parse_json_body(&body) {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    // Validate the public key
+    let public_key: [u8; 32] = match base64::decode(&body.public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .filter(is_usable_recipient_key)
+    {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "public_key must be a base64-encoded 32-byte X25519 key of full order"
+            }))
+        }
+    };
+
+    let key = match register_recipient_key(db_pool, &principal.tenant, &recipient_id, public_key).await {
+        Ok(key) => key,
+        Err(e) => return storage_error("Failed to register recipient key", e),
+    };
+
+    auth.audit.record(AuditEntry {
+        principal: Some(principal.subject),
+        outcome: "success".to_string(),
+        details: serde_json::json!({ "recipient_id": recipient_id.as_str(), "key_id": key.id }),
+        ..AuditEntry::for_request(&req, "recipient_key.register")
+    });
+
+    HttpResponse::Created().json(key.to_json())
+}
+
+// List a recipient's public keys, including revoked ones
+async fn get_recipient_keys(
+    req: web::HttpRequest,
+    recipient_id: web::Path<String>,
+    db_pool: web::Data<Option<PgPool>>,
+    auth: web::Data<Authenticator>,
+) -> HttpResponse {
+    let principal = match authenticate_request(&req, &[], &auth).await {
+        Ok(principal) => principal,
+        Err(e) => return e.response(),
+    };
+    if let Err(response) = require_scope(&principal, SCOPE_RECIPIENT_KEYS) {
+        return response;
+    }
+    // Recipient keys and webhooks live only in Postgres
+    let db_pool = match db_pool.get_ref() {
+        Some(db_pool) => db_pool,
+        None => return storage_disabled(),
+    };
+
+    let keys = match list_recipient_keys(db_pool, &principal.tenant, &recipient_id).await {
+        Ok(keys) => keys,
+        Err(e) => return storage_error("Failed to list recipient keys", e),
+    };
+
+    HttpResponse::Ok().json(keys.iter().map(RecipientKey::to_json).collect::<Vec<_>>())
+}
+
+// Revoke a recipient public key
+async fn delete_recipient_key(
+    req: web::HttpRequest,
+    path: web::Path<(String, Uuid)>,
+    db_pool: web::Data<Option<PgPool>>,
+    auth: web::Data<Authenticator>,
+) -> HttpResponse {
+    let principal = match authenticate_request(&req, &[], &auth).await {
+        Ok(principal) => principal,
+        Err(e) => return e.response(),
+    };
+    if let Err(response) = require_scope(&principal, SCOPE_RECIPIENT_KEYS) {
+        return response;
+    }
+    // Recipient keys and webhooks live only in Postgres
+    let db_pool = match db_pool.get_ref() {
+        Some(db_pool) => db_pool,
+        None => return storage_disabled(),
+    };
+
+    let (recipient_id, key_id) = path.into_inner();
+    let revoked = match revoke_recipient_key(db_pool, &principal.tenant, &recipient_id, key_id).await {
+        Ok(revoked) => revoked,
+        Err(e) => return storage_error("Failed to revoke recipient key", e),
+    };
+
+    auth.audit.record(AuditEntry {
+        principal: Some(principal.subject),
+        outcome: if revoked { "success" } else { "not_found" }.to_string(),
+        details: serde_json::json!({ "recipient_id": recipient_id, "key_id": key_id }),
+        ..AuditEntry::for_request(&req, "recipient_key.revoke")
+    });
+
+    if revoked {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+// Headers carrying the detached signature of a delivered notification
+const SIGNATURE_HEADER: &str = "X-Notifier-Signature";
+const SIGNATURE_KEY_ID_HEADER: &str = "X-Notifier-Key-Id";
+
+// Notification payload with its detached Ed25519 signature
+struct SignedNotification {
+    payload: Vec<u8>,
+    key_id: String,
+    signature: Signature,
+}
+
+impl SignedNotification {
+    // Headers to send alongside the payload
+    fn headers(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (SIGNATURE_HEADER, base64::encode_config(self.signature.to_bytes(), base64::URL_SAFE_NO_PAD)),
+            (SIGNATURE_KEY_ID_HEADER, self.key_id.clone()),
+        ]
+    }
+}
+
+// Notification Signer: signs outbound payloads with the active Ed25519 key.
+// Older keys stay published in the JWKS so in-flight signatures still verify.
+struct NotificationSigner {
+    active_key_id: String,
+    keys: HashMap<String, SigningKey>,
+}
+
+impl NotificationSigner {
+    // Load signing keys from a key file holding base64-encoded 32-byte Ed25519 seeds
+    fn load(path: &Path) -> Result<Self, CryptoError> {
+        let file = KeyFile::read(path)?;
+
+        let mut keys = HashMap::new();
+        for (id, encoded) in &file.keys {
+            let seed: [u8; 32] = base64::decode(encoded)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or(CryptoError::InvalidKey)?;
+            keys.insert(id.clone(), SigningKey::from_bytes(&seed));
+        }
+        if !keys.contains_key(&file.active_key_id) {
+            return Err(CryptoError::UnknownKey(file.active_key_id));
+        }
+
+        Ok(NotificationSigner {
+            active_key_id: file.active_key_id,
+            keys,
+        })
+    }
+
+    fn sign(&self, payload: Vec<u8>) -> SignedNotification {
+        let signature = self.keys[&self.active_key_id].sign(&payload);
+
+        SignedNotification {
+            payload,
+            key_id: self.active_key_id.clone(),
+            signature,
+        }
+    }
+
+    // Public keys in JWKS form
+    fn jwks(&self) -> Jwks {
+        let mut keys: Vec<Jwk> = self
+            .keys
+            .iter()
+            .map(|(id, key)| Jwk {
+                kty: "OKP".to_string(),
+                crv: "Ed25519".to_string(),
+                x: base64::encode_config(key.verifying_key().as_bytes(), base64::URL_SAFE_NO_PAD),
+                kid: id.clone(),
+                key_use: Some("sig".to_string()),
+                alg: Some("EdDSA".to_string()),
+            })
+            .collect();
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+
+        Jwks { keys }
+    }
+}
+
+// JSON Web Key Set, as published at /.well-known/jwks.json
+#[derive(Serialize, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+// Ed25519 public key in JWK form (RFC 8037)
+#[derive(Serialize, Deserialize)]
+struct Jwk {
+    kty: String,
+    crv: String,
+    // Base64url-encoded public key
+    x: String,
+    kid: String,
+    #[serde(rename = "use", default, skip_serializing_if = "Option::is_none")]
+    key_use: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alg: Option<String>,
+}
+
+// Verify a delivered notification against our published JWKS.
+// `signature` and `key_id` are the values of the signature headers.
+fn verify_notification(payload: &[u8], signature: &str, key_id: &str, jwks: &Jwks) -> Result<(), CryptoError> {
+    let jwk = jwks
+        .keys
+        .iter()
+        .find(|jwk| jwk.kid == key_id)
+        .ok_or_else(|| CryptoError::UnknownKey(key_id.to_string()))?;
+    if jwk.kty != "OKP" || jwk.crv != "Ed25519" {
+        return Err(CryptoError::UnsupportedAlgorithm(format!("{}/{}", jwk.kty, jwk.crv)));
+    }
+
+    // Decode the public key and signature
+    let public_key: [u8; 32] = base64::decode_config(&jwk.x, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(CryptoError::InvalidKey)?;
+    let verifying_key = VerifyingKey::from_bytes(&public_key).map_err(|_| CryptoError::InvalidKey)?;
+    let signature: [u8; 64] = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(CryptoError::InvalidSignature)?;
+
+    verifying_key
+        .verify_strict(payload, &Signature::from_bytes(&signature))
+        .map_err(|_| CryptoError::InvalidSignature)
+}
+
+// Publish our notification signing keys
+async fn jwks_endpoint(signer: web::Data<NotificationSigner>) -> HttpResponse {
+    HttpResponse::Ok().json(signer.jwks())
+}
+
+// What a channel needs in order to deliver a notification
+#[derive(Clone, Copy, Debug, Serialize)]
+struct ChannelCapabilities {
+    // Renders the event's content, so it must see the plaintext. Such channels
+    // are skipped for events sealed to a recipient's key.
+    requires_plaintext: bool,
+    // Delivers to a recipient, so events without one are skipped
+    requires_recipient: bool,
+}
+
+// Everything a channel may use to deliver one notification
+struct Delivery<'a> {
+    tenant_id: &'a str,
+    // Event for channels that render content, without its policy-encrypted fields
+    event: &'a Event,
+    encrypted: &'a EncryptedEvent,
+    signed: &'a SignedNotification,
+    // The tenant's credentials for this channel, if configured
+    credentials: Option<&'a HashMap<String, String>>,
+}
+
+// Why a channel failed to deliver
+#[derive(Debug)]
+enum ChannelError {
+    Misconfigured(String),
+    Transport(String),
+    Rejected(String),
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelError::Misconfigured(reason) => write!(f, "channel misconfigured: {}", reason),
+            ChannelError::Transport(reason) => write!(f, "transport error: {}", reason),
+            ChannelError::Rejected(reason) => write!(f, "rejected: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {}
+
+// Notification Channel: one way of delivering notifications
+#[async_trait]
+trait NotificationChannel: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn capabilities(&self) -> ChannelCapabilities;
+
+    // Deliver one notification, returning a channel-specific receipt on success
+    async fn send(&self, delivery: &Delivery<'_>) -> Result<String, ChannelError>;
+
+    // Whether the channel can currently deliver
+    async fn health(&self) -> Result<(), ChannelError>;
+}
+
+// Outcome of delivering a notification through one channel
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum DeliveryOutcome {
+    Delivered { receipt: String },
+    Skipped { reason: String },
+    Failed { error: String },
+}
+
+#[derive(Serialize)]
+struct ChannelDelivery {
+    channel: String,
+    #[serde(flatten)]
+    outcome: DeliveryOutcome,
+}
+
+// Channel Registry: the configured channels and which of them each event type uses
+struct ChannelRegistry {
+    channels: HashMap<String, Arc<dyn NotificationChannel>>,
+    // Event type -> channel names
+    routes: HashMap<String, Vec<String>>,
+    // Channels for event types without a route
+    default_route: Vec<String>,
+    // Tenant id -> channel name -> credentials
+    credentials: HashMap<String, HashMap<String, HashMap<String, String>>>,
+    // Channels left out at startup -> why, reported as the skip reason
+    disabled: HashMap<String, String>,
+}
+
+impl ChannelRegistry {
+    fn from_config(config: &Config) -> Self {
+        ChannelRegistry {
+            channels: HashMap::new(),
+            routes: config.channel_routes.clone(),
+            default_route: config.default_channels.clone(),
+            credentials: config
+                .tenants
+                .iter()
+                .map(|(tenant_id, tenant)| (tenant_id.clone(), tenant.channel_credentials.clone()))
+                .collect(),
+            disabled: HashMap::new(),
+        }
+    }
+
+    fn register(&mut self, channel: Arc<dyn NotificationChannel>) {
+        self.channels.insert(channel.name().to_string(), channel);
+    }
+
+    // Leave out a channel that can't run in this deployment; events routed to
+    // it are skipped with `reason` rather than failed
+    fn disable(&mut self, name: &str, reason: &str) {
+        self.disabled.insert(name.to_string(), reason.to_string());
+    }
+
+    fn route(&self, event_type: &str) -> &[String] {
+        self.routes.get(event_type).unwrap_or(&self.default_route)
+    }
+
+    fn credentials(&self, tenant_id: &str, channel: &str) -> Option<&HashMap<String, String>> {
+        self.credentials.get(tenant_id).and_then(|channels| channels.get(channel))
+    }
+}
+
+// Send Notification through every channel the event is routed to, using the
+// tenant's channel credentials. Channels are tried concurrently.
+async fn send_notification(
+    channels: &ChannelRegistry,
+    tenant_id: &str,
+    event: &Event,
+    encrypted: &EncryptedEvent,
+    notification: &SignedNotification,
+) -> Vec<ChannelDelivery> {
+    // Only the recipient can read a sealed event, so it must not be rendered in clear
+    let sealed_to_recipient = encrypted.wrapped_key.ephemeral_public_key.is_some();
+
+    let deliveries = channels.route(&event.event_type).iter().map(|name| async move {
+        let outcome = match channels.channels.get(name) {
+            None if channels.disabled.contains_key(name) => DeliveryOutcome::Skipped {
+                reason: channels.disabled[name].clone(),
+            },
+            None => DeliveryOutcome::Failed {
+                error: format!("unknown channel: {}", name),
+            },
+            Some(channel) => {
+                let capabilities = channel.capabilities();
+                if capabilities.requires_plaintext && sealed_to_recipient {
+                    DeliveryOutcome::Skipped {
+                        reason: "sealed_to_recipient".to_string(),
+                    }
+                } else if capabilities.requires_recipient && event.recipient.is_none() {
+                    DeliveryOutcome::Skipped {
+                        reason: "no_recipient".to_string(),
+                    }
+                } else {
+                    let delivery = Delivery {
+                        tenant_id,
+                        event,
+                        encrypted,
+                        signed: notification,
+                        credentials: channels.credentials(tenant_id, name),
+                    };
+                    match channel.send(&delivery).await {
+                        Ok(receipt) => DeliveryOutcome::Delivered { receipt },
+                        Err(e) => DeliveryOutcome::Failed { error: e.to_string() },
+                    }
+                }
+            }
+        };
+
+        ChannelDelivery {
+            channel: name.clone(),
+            outcome,
+        }
+    });
+
+    join_all(deliveries).await
+}
+
+// Channel health: whether each configured channel can deliver. Failure
+// details are only shown to key admins.
+async fn channel_health(
+    req: web::HttpRequest,
+    channels: web::Data<ChannelRegistry>,
+    auth: web::Data<Authenticator>,
+) -> HttpResponse {
+    let details = shows_health_details(&req, &auth).await;
+    let checks = channels.channels.iter().map(|(name, channel)| async move {
+        let status = match channel.health().await {
+            Ok(()) => serde_json::json!({ "status": "ok" }),
+            Err(e) => {
+                eprintln!("Channel {} is unavailable: {}", name, e);
+                if details {
+                    serde_json::json!({ "status": "unavailable", "error": e.to_string() })
+                } else {
+                    serde_json::json!({ "status": "unavailable" })
+                }
+            }
+        };
+        (name.clone(), status)
+    });
+
+    let statuses: serde_json::Map<String, Value> = join_all(checks).await.into_iter().collect();
+    HttpResponse::Ok().json(statuses)
+}
+
+// Headers added to every webhook delivery
+const WEBHOOK_SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+const WEBHOOK_EVENT_ID_HEADER: &str = "X-Webhook-Event-Id";
+const WEBHOOK_ATTEMPT_HEADER: &str = "X-Webhook-Attempt";
+
+// Webhook delivery settings
+#[derive(Clone)]
+struct WebhookConfig {
+    // Attempts per endpoint before giving up, including the first
+    max_attempts: u32,
+    // Delay before the first retry; doubles with each further retry
+    retry_backoff: Duration,
+    // Timeout for endpoints registered without one
+    default_timeout: Duration,
+    // Longest per-request timeout an endpoint may register
+    max_timeout: Duration,
+    // Budget for all attempts to one endpoint, backoff included. Deliveries
+    // run inside the /api/notify request, so this bounds how long it waits.
+    delivery_deadline: Duration,
+    // Accept plain http URLs and hosts resolving to loopback or private
+    // addresses, for development against a local listener. Never in production.
+    allow_private_targets: bool,
+}
+
+// Webhook clients kept for reuse, one per host and resolved address set
+const WEBHOOK_CLIENT_CACHE_CAPACITY: usize = 1024;
+
+// Subscriber URL registered by a tenant
+struct WebhookEndpoint {
+    id: Uuid,
+    tenant_id: String,
+    url: String,
+    // Event types delivered to this endpoint; empty means all
+    event_types: Vec<String>,
+    timeout: Duration,
+    // HMAC secret, wrapped by the tenant's KeyProvider
+    secret: WrappedKey,
+    created_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookEndpoint {
+    fn from_row(row: &PgRow) -> Self {
+        let timeout_ms: i64 = row.get("timeout_ms");
+        let Json(secret) = row.get("secret");
+
+        WebhookEndpoint {
+            id: row.get("id"),
+            tenant_id: row.get("tenant_id"),
+            url: row.get("url"),
+            event_types: row.get("event_types"),
+            timeout: Duration::from_millis(timeout_ms.max(1) as u64),
+            secret,
+            created_at: row.get("created_at"),
+            revoked_at: row.get("revoked_at"),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "id": self.id,
+            "tenant_id": self.tenant_id,
+            "url": self.url,
+            "event_types": self.event_types,
+            "timeout_ms": self.timeout.as_millis() as u64,
+            "created_at": self.created_at,
+            "revoked_at": self.revoked_at,
+        })
+    }
+}
+
+// Webhook Registry: a tenant's unrevoked endpoints subscribed to `event_type`
+async fn webhook_endpoints_for(pool: &PgPool, tenant_id: &str, event_type: &str) -> Result<Vec<WebhookEndpoint>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT * FROM webhook_endpoints
+         WHERE tenant_id = $1 AND revoked_at IS NULL
+           AND (cardinality(event_types) = 0 OR $2 = ANY(event_types))",
+    )
+    .bind(tenant_id)
+    .bind(event_type)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(WebhookEndpoint::from_row).collect())
+}
+
+async fn list_webhook_endpoints(pool: &PgPool, tenant_id: &str) -> Result<Vec<WebhookEndpoint>, sqlx::Error> {
+    let rows = sqlx::query("SELECT * FROM webhook_endpoints WHERE tenant_id = $1 ORDER BY created_at DESC")
+        .bind(tenant_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows.iter().map(WebhookEndpoint::from_row).collect())
+}
+
+async fn register_webhook_endpoint(
+    pool: &PgPool,
+    tenant_id: &str,
+    url: &str,
+    event_types: &[String],
+    timeout: Duration,
+    secret: &WrappedKey,
+) -> Result<WebhookEndpoint, sqlx::Error> {
+    let row = sqlx::query(
+        "INSERT INTO webhook_endpoints (id, tenant_id, url, event_types, timeout_ms, secret)
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+    )
+    .bind(Uuid::new_v4())
+    .bind(tenant_id)
+    .bind(url)
+    .bind(event_types)
+    .bind(timeout.as_millis() as i64)
+    .bind(Json(secret))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(WebhookEndpoint::from_row(&row))
+}
+
+// Returns false when no such unrevoked endpoint exists
+async fn revoke_webhook_endpoint(pool: &PgPool, tenant_id: &str, id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE webhook_endpoints SET revoked_at = now()
+         WHERE id = $1 AND tenant_id = $2 AND revoked_at IS NULL",
+    )
+    .bind(id)
+    .bind(tenant_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Whether a webhook may be delivered to `ip`. Loopback, private, link-local
+// (which includes cloud metadata at 169.254.169.254) and other non-routable
+// ranges are refused so tenants can't point the service at its own network.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // "This network", 0.0.0.0/8
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64)
+                // IETF protocol assignments, 192.0.0.0/24
+                || (a == 192 && b == 0 && c == 0)
+                // Benchmarking, 198.18.0.0/15
+                || (a == 198 && b & 0xfe == 18)
+                // Reserved, 240.0.0.0/4
+                || a >= 240)
+        }
+        // IPv4-mapped and -compatible addresses reach the IPv4 address they embed
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            Some(ip) => is_public_address(IpAddr::V4(ip)),
+            None => {
+                let [a, b, ..] = ip.segments();
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7
+                    || a & 0xfe00 == 0xfc00
+                    // Link-local, fe80::/10
+                    || a & 0xffc0 == 0xfe80
+                    // Documentation, 2001:db8::/32
+                    || (a == 0x2001 && b == 0x0db8)
+                    // NAT64, 64:ff9b::/96, which translates to any IPv4 address
+                    || (a == 0x0064 && b == 0xff9b && ip.segments()[2..6] == [0, 0, 0, 0])
+                    // 6to4, 2002::/16, which embeds an IPv4 relay
+                    || a == 0x2002)
+            }
+        },
+    }
+}
+
+// Parse a webhook URL and resolve its host, failing unless it is https and
+// every address the host resolves to is public. `allow_private` lifts both
+// checks; see WebhookConfig.allow_private_targets.
+async fn resolve_webhook_target(url: &str, allow_private: bool) -> Result<(reqwest::Url, Vec<SocketAddr>), String> {
+    let url = match reqwest::Url::parse(url) {
+        Ok(url) if url.scheme() == "https" || (allow_private && url.scheme() == "http") => url,
+        _ => return Err("url must be an absolute https URL".to_string()),
+    };
+    let host = match url.host_str() {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']').to_string(),
+        None => return Err("url must name a host".to_string()),
+    };
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let mut addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+        .collect();
+    addrs.sort();
+    addrs.dedup();
+    if addrs.is_empty() {
+        return Err(format!("{} does not resolve to any address", host));
+    }
+    if allow_private {
+        return Ok((url, addrs));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_address(addr.ip())) {
+        return Err(format!("{} resolves to {}, which is not a public address", host, addr.ip()));
+    }
+    Ok((url, addrs))
+}
+
+// Webhook Channel: POSTs the signed, encrypted event to each subscribed endpoint.
+// Consumers verify X-Webhook-Signature, which is
+// "sha256=" + hex(HMAC-SHA256(endpoint secret, timestamp + "." + body)).
+struct WebhookChannel {
+    pool: PgPool,
+    keys: Arc<TenantKeys>,
+    config: WebhookConfig,
+    // Host and resolved addresses -> client pinned to them, so deliveries to
+    // the same endpoint share connections
+    clients: Mutex<HashMap<(String, Vec<SocketAddr>), reqwest::Client>>,
+}
+
+impl WebhookChannel {
+    fn new(pool: PgPool, keys: Arc<TenantKeys>, config: WebhookConfig) -> Self {
+        WebhookChannel {
+            pool,
+            keys,
+            config,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Client connecting only to the addresses that were just checked, trying
+    // each in turn, so a DNS answer changed after the check can't redirect it.
+    // It never follows redirects, which could lead anywhere.
+    fn pinned_client(&self, url: &reqwest::Url, addrs: &[SocketAddr]) -> Result<reqwest::Client, ChannelError> {
+        let host = url.host_str().expect("Webhook targets have a host");
+        let cache_key = (host.to_string(), addrs.to_vec());
+
+        let mut clients = self.clients.lock().expect("Webhook client cache lock poisoned");
+        if let Some(client) = clients.get(&cache_key) {
+            return Ok(client.clone());
+        }
+
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .resolve_to_addrs(host, addrs)
+            .build()
+            .map_err(|e| ChannelError::Misconfigured(e.to_string()))?;
+        // Hosts whose addresses change leave stale entries behind; start over when full
+        if clients.len() >= WEBHOOK_CLIENT_CACHE_CAPACITY {
+            clients.clear();
+        }
+        clients.insert(cache_key, client.clone());
+        Ok(client)
+    }
+
+    // Deliver to one endpoint, retrying transport errors, 429s and 5xx responses
+    // until the attempts or the delivery deadline run out
+    async fn deliver(&self, endpoint: &WebhookEndpoint, delivery: &Delivery<'_>) -> Result<(), ChannelError> {
+        let secret = self
+            .keys
+            .for_tenant(&endpoint.tenant_id)
+            .unwrap_key(&endpoint.secret)
+            .map_err(|e| ChannelError::Misconfigured(e.to_string()))?;
+        let payload = &delivery.signed.payload;
+
+        let (url, addrs) = resolve_webhook_target(&endpoint.url, self.config.allow_private_targets)
+            .await
+            .map_err(|e| ChannelError::Rejected(format!("{}: {}", endpoint.url, e)))?;
+        let client = self.pinned_client(&url, &addrs)?;
+        let deadline = Instant::now() + self.config.delivery_deadline;
+
+        let mut last_error = None;
+        for attempt in 1..=self.config.max_attempts.max(1) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                break;
+            }
+
+            // Sign each attempt afresh so its timestamp is current
+            let timestamp = Utc::now().timestamp().to_string();
+            let mut mac = HmacSha256::new_from_slice(&secret).expect("HMAC accepts any key length");
+            mac.update(timestamp.as_bytes());
+            mac.update(b".");
+            mac.update(payload);
+            let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+            let mut request = client
+                .post(url.clone())
+                .timeout(endpoint.timeout.min(remaining))
+                .header("Content-Type", "application/json")
+                .header(WEBHOOK_SIGNATURE_HEADER, signature)
+                .header(WEBHOOK_TIMESTAMP_HEADER, &timestamp)
+                .header(WEBHOOK_EVENT_ID_HEADER, delivery.encrypted.id.to_string())
+                .header(WEBHOOK_ATTEMPT_HEADER, attempt.to_string());
+            for (name, value) in delivery.signed.headers() {
+                request = request.header(name, value);
+            }
+
+            let error = match request.body(payload.clone()).send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let error = ChannelError::Rejected(format!("{} answered {}", endpoint.url, status));
+                    // Redirects aren't followed and other client errors won't succeed on retry
+                    if (status.is_redirection() || status.is_client_error()) && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(e) => ChannelError::Transport(format!("{}: {}", endpoint.url, e)),
+            };
+            last_error = Some(error);
+
+            if attempt < self.config.max_attempts {
+                let backoff = self
+                    .config
+                    .retry_backoff
+                    .checked_mul(1 << (attempt - 1).min(16))
+                    .unwrap_or(self.config.delivery_deadline);
+                // Don't sleep past the deadline only to give up afterwards
+                if Instant::now() + backoff >= deadline {
+                    break;
+                }
+                tokio::time::sleep(backoff).await;
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            ChannelError::Transport(format!("{}: delivery deadline passed", endpoint.url))
+        }))
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for WebhookChannel {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn capabilities(&self) -> ChannelCapabilities {
+        // Subscribers receive the encrypted event, never the plaintext
+        ChannelCapabilities {
+            requires_plaintext: false,
+            requires_recipient: false,
+        }
+    }
+
+    async fn send(&self, delivery: &Delivery<'_>) -> Result<String, ChannelError> {
+        let endpoints = webhook_endpoints_for(&self.pool, delivery.tenant_id, &delivery.event.event_type)
+            .await
+            .map_err(|e| ChannelError::Misconfigured(e.to_string()))?;
+
+        let results = join_all(endpoints.iter().map(|endpoint| self.deliver(endpoint, delivery))).await;
+        let failures: Vec<String> = results
+            .into_iter()
+            .filter_map(|result| result.err().map(|e| e.to_string()))
+            .collect();
+
+        if failures.is_empty() {
+            Ok(format!("delivered to {} endpoint(s)", endpoints.len()))
+        } else {
+            Err(ChannelError::Rejected(format!(
+                "{} of {} endpoint(s) failed: {}",
+                failures.len(),
+                endpoints.len(),
+                failures.join("; ")
+            )))
+        }
+    }
+
+    async fn health(&self) -> Result<(), ChannelError> {
+        // Endpoints are looked up per delivery, so the registry must be reachable
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| ChannelError::Transport(e.to_string()))
+    }
+}
+
+// How the email channel secures its connection to the relay
+#[derive(Clone, Copy, Debug)]
+enum SmtpSecurity {
+    // Plain SMTP, for a local sink during development only
+    None,
+    // Upgrade a plain connection with STARTTLS (usually port 587)
+    StartTls,
+    // TLS from the first byte (usually port 465)
+    Tls,
+}
+
+// Subject and bodies rendered for one event type. Placeholders such as
+// `{{event_type}}` or `{{data.user.name}}` are replaced with event fields;
+// missing fields render empty.
+#[derive(Clone)]
+struct EmailTemplate {
+    subject: String,
+    text: String,
+    // Sent as an alternative to `text` when set; placeholders are HTML-escaped
+    html: Option<String>,
+}
+
+// Email channel settings. Tenants may override `username`, `password`, `from`
+// and `to` (comma-separated) in their "email" channel credentials.
+#[derive(Clone)]
+struct EmailConfig {
+    relay: String,
+    port: u16,
+    security: SmtpSecurity,
+    username: Option<String>,
+    password: Option<String>,
+    from: String,
+    to: Vec<String>,
+    timeout: Duration,
+    // Event type -> template
+    templates: HashMap<String, EmailTemplate>,
+    // Template for event types without one
+    default_template: EmailTemplate,
+}
+
+// Render a template against an event. Unknown or missing fields render empty,
+// as do fields encrypted by the event type's field policy, which are redacted
+// before any channel sees the event; under the default policy that is all of
+// `data`. Strings render bare and other JSON values as JSON. `escape`, if
+// given, is applied to every substituted value.
+fn render_template(template: &str, event: &Event, escape: Option<fn(&str) -> String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let end = match rest[start + 2..].find("}}") {
+            Some(end) => end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let path = rest[start + 2..start + 2 + end].trim();
+        let value = template_value(event, path);
+        match escape {
+            Some(escape) => rendered.push_str(&escape(&value)),
+            None => rendered.push_str(&value),
+        }
+        rest = &rest[start + 2 + end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn template_value(event: &Event, path: &str) -> String {
+    let value = match path {
+        "id" => return event.id.to_string(),
+        "event_type" => return event.event_type.clone(),
+        "recipient" => return event.recipient.clone().unwrap_or_default(),
+        // Only named fields, so a template can't dump the whole payload
+        _ => path.strip_prefix("data.").and_then(|path| json_at(&event.data, path)),
+    };
+
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+// Look up a dotted path such as `user.emails.0` in a JSON value
+fn json_at<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, segment| match value {
+        Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => value.get(segment),
+    })
+}
+
+fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Email transports kept for reuse, one per relay login
+const EMAIL_TRANSPORT_CACHE_CAPACITY: usize = 1024;
+
+// Email Channel: renders the event through its template and sends it to the
+// configured SMTP relay
+struct EmailChannel {
+    config: EmailConfig,
+    // Relay login -> transport using it, so sends with the same credentials
+    // share its connection pool
+    transports: Mutex<HashMap<Option<(String, String)>, AsyncSmtpTransport<Tokio1Executor>>>,
+}
+
+impl EmailChannel {
+    fn new(config: EmailConfig) -> Self {
+        EmailChannel {
+            config,
+            transports: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // A tenant's credentials override the configured relay login
+    fn transport(&self, credentials: Option<&HashMap<String, String>>) -> Result<AsyncSmtpTransport<Tokio1Executor>, ChannelError> {
+        let setting = |name: &str| credentials.and_then(|c| c.get(name)).cloned();
+        let username = setting("username").or_else(|| self.config.username.clone());
+        let password = setting("password").or_else(|| self.config.password.clone());
+        let login = match (username, password) {
+            (Some(username), Some(password)) => Some((username, password)),
+            _ => None,
+        };
+
+        let mut transports = self.transports.lock().expect("Email transport cache lock poisoned");
+        if let Some(transport) = transports.get(&login) {
+            return Ok(transport.clone());
+        }
+
+        let relay = &self.config.relay;
+        let builder = match self.config.security {
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(relay),
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(relay)
+                .map_err(|e| ChannelError::Misconfigured(e.to_string()))?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(relay)
+                .map_err(|e| ChannelError::Misconfigured(e.to_string()))?,
+        };
+        let mut builder = builder.port(self.config.port).timeout(Some(self.config.timeout));
+        if let Some((username, password)) = &login {
+            builder = builder.credentials(SmtpCredentials::new(username.clone(), password.clone()));
+        }
+
+        let transport = builder.build();
+        // Logins no longer in use would pile up; start over when full
+        if transports.len() >= EMAIL_TRANSPORT_CACHE_CAPACITY {
+            transports.clear();
+        }
+        transports.insert(login, transport.clone());
+        Ok(transport)
+    }
+
+    fn message(&self, delivery: &Delivery<'_>) -> Result<Message, ChannelError> {
+        let event = delivery.event;
+        let template = self
+            .config
+            .templates
+            .get(&event.event_type)
+            .unwrap_or(&self.config.default_template);
+
+        let setting = |name: &str| delivery.credentials.and_then(|c| c.get(name));
+        let from = setting("from").unwrap_or(&self.config.from);
+        let to: Vec<&str> = match setting("to") {
+            Some(to) => to.split(',').map(str::trim).filter(|to| !to.is_empty()).collect(),
+            None => self.config.to.iter().map(String::as_str).collect(),
+        };
+        if to.is_empty() {
+            return Err(ChannelError::Misconfigured("no email recipients configured".to_string()));
+        }
+
+        let mailbox = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| ChannelError::Misconfigured(format!("invalid address {}: {}", address, e)))
+        };
+        let mut builder = Message::builder()
+            .from(mailbox(from)?)
+            .subject(render_template(&template.subject, event, None));
+        for address in to {
+            builder = builder.to(mailbox(address)?);
+        }
+
+        let text = render_template(&template.text, event, None);
+        let message = match &template.html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+                text,
+                render_template(html, event, Some(html_escape)),
+            )),
+            None => builder.singlepart(SinglePart::plain(text)),
+        };
+        message.map_err(|e| ChannelError::Misconfigured(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for EmailChannel {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn capabilities(&self) -> ChannelCapabilities {
+        // Templates render event content
+        ChannelCapabilities {
+            requires_plaintext: true,
+            requires_recipient: false,
+        }
+    }
+
+    async fn send(&self, delivery: &Delivery<'_>) -> Result<String, ChannelError> {
+        let message = self.message(delivery)?;
+        let transport = self.transport(delivery.credentials)?;
+
+        match transport.send(message).await {
+            Ok(response) => Ok(response.first_line().unwrap_or("accepted").to_string()),
+            Err(e) if e.is_permanent() => Err(ChannelError::Rejected(e.to_string())),
+            Err(e) => Err(ChannelError::Transport(e.to_string())),
+        }
+    }
+
+    async fn health(&self) -> Result<(), ChannelError> {
+        match self.transport(None)?.test_connection().await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(ChannelError::Transport(format!("{} did not answer NOOP", self.config.relay))),
+            Err(e) => Err(ChannelError::Transport(e.to_string())),
+        }
+    }
+}
+
+// Payload format expected by a chat service's incoming webhook
+#[derive(Clone, Copy, Debug)]
+enum ChatFormat {
+    // Slack Block Kit
+    Slack,
+    // Legacy Office 365 connector card
+    TeamsMessageCard,
+    // Adaptive Card, for Teams workflows
+    TeamsAdaptiveCard,
+    // Discord embed
+    Discord,
+}
+
+// Event field shown as a labelled fact
+#[derive(Clone)]
+struct ChatField {
+    label: String,
+    // Template path, e.g. `data.user.name`; fields that render empty are left out
+    path: String,
+}
+
+// How one event type is laid out in a chat message. `title` and `text` are
+// templates as in EmailTemplate.
+#[derive(Clone)]
+struct ChatFormatting {
+    title: String,
+    text: String,
+    fields: Vec<ChatField>,
+    // Accent colour as `#rrggbb`, where the format supports one
+    color: Option<String>,
+}
+
+// One chat channel. Tenants supply the incoming-webhook URL as `webhook_url`
+// in their credentials for the channel; `webhook_url` here is the fallback.
+#[derive(Clone)]
+struct ChatChannelConfig {
+    name: String,
+    format: ChatFormat,
+    webhook_url: Option<String>,
+    // Event type -> formatting
+    formatting: HashMap<String, ChatFormatting>,
+    // Formatting for event types without their own
+    default_formatting: ChatFormatting,
+    // Attempts per message when rate limited, including the first
+    max_attempts: u32,
+    // Longest Retry-After honoured; longer waits fail the delivery instead
+    max_retry_wait: Duration,
+    // Timeout for each request to the webhook
+    timeout: Duration,
+}
+
+// Escape the characters Slack treats as markup in mrkdwn text
+fn slack_escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Escape Discord markdown so event data renders literally
+fn discord_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '|' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Escape the markdown Teams renders in card text and fact values. Markup
+// characters are backslash-escaped; `<`, `>` and `&` become entities, since
+// MessageCards also accept HTML.
+fn teams_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\\' | '*' | '_' | '~' | '`' | '[' | ']' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Cut `value` to at most `max` characters, marking the cut with an ellipsis
+fn truncate_chars(value: &str, max: usize) -> String {
+    if value.chars().count() <= max {
+        return value.to_string();
+    }
+    let mut truncated: String = value.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+// Build the webhook payload for `format`, within each service's size limits
+fn chat_payload(format: ChatFormat, formatting: &ChatFormatting, event: &Event) -> Value {
+    let escape = match format {
+        ChatFormat::Slack => Some(slack_escape as fn(&str) -> String),
+        ChatFormat::Discord => Some(discord_escape as fn(&str) -> String),
+        ChatFormat::TeamsMessageCard | ChatFormat::TeamsAdaptiveCard => Some(teams_escape as fn(&str) -> String),
+    };
+    let title = render_template(&formatting.title, event, None);
+    let text = render_template(&formatting.text, event, escape);
+    let fields: Vec<(String, String)> = formatting
+        .fields
+        .iter()
+        .map(|field| {
+            let value = template_value(event, &field.path);
+            let value = match escape {
+                Some(escape) => escape(&value),
+                None => value,
+            };
+            (field.label.clone(), value)
+        })
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    let color = formatting.color.as_deref().map(|color| color.trim_start_matches('#'));
+
+    match format {
+        ChatFormat::Slack => {
+            let mut blocks = vec![
+                serde_json::json!({
+                    "type": "header",
+                    "text": { "type": "plain_text", "text": truncate_chars(&title, 150) },
+                }),
+                serde_json::json!({
+                    "type": "section",
+                    "text": { "type": "mrkdwn", "text": truncate_chars(&text, 3000) },
+                }),
+            ];
+            // A section holds at most 10 fields
+            for chunk in fields.chunks(10) {
+                let fields: Vec<Value> = chunk
+                    .iter()
+                    .map(|(label, value)| {
+                        serde_json::json!({
+                            "type": "mrkdwn",
+                            "text": truncate_chars(&format!("*{}*\n{}", slack_escape(label), value), 2000),
+                        })
+                    })
+                    .collect();
+                blocks.push(serde_json::json!({ "type": "section", "fields": fields }));
+            }
+            blocks.push(serde_json::json!({
+                "type": "context",
+                "elements": [{ "type": "mrkdwn", "text": format!("{} · {}", slack_escape(&event.event_type), event.id) }],
+            }));
+            // Blocks replace `text`, which remains the notification preview and is
+            // read as mrkdwn like the blocks
+            serde_json::json!({ "text": truncate_chars(&slack_escape(&title), 3000), "blocks": blocks })
+        }
+        ChatFormat::TeamsMessageCard => {
+            let facts: Vec<Value> = fields
+                .iter()
+                .map(|(label, value)| serde_json::json!({ "name": label, "value": value }))
+                .collect();
+            let mut card = serde_json::json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": truncate_chars(&title, 256),
+                "title": teams_escape(&title),
+                "text": text,
+                "sections": [{ "facts": facts }],
+            });
+            if let Some(color) = color {
+                card["themeColor"] = Value::String(color.to_string());
+            }
+            card
+        }
+        ChatFormat::TeamsAdaptiveCard => {
+            let facts: Vec<Value> = fields
+                .iter()
+                .map(|(label, value)| serde_json::json!({ "title": label, "value": value }))
+                .collect();
+            serde_json::json!({
+                "type": "message",
+                "attachments": [{
+                    "contentType": "application/vnd.microsoft.card.adaptive",
+                    "content": {
+                        "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                        "type": "AdaptiveCard",
+                        "version": "1.4",
+                        "body": [
+                            { "type": "TextBlock", "text": teams_escape(&title), "size": "Large", "weight": "Bolder", "wrap": true },
+                            { "type": "TextBlock", "text": text, "wrap": true },
+                            { "type": "FactSet", "facts": facts },
+                        ],
+                    },
+                }],
+            })
+        }
+        ChatFormat::Discord => {
+            // An embed holds at most 25 fields
+            let embed_fields: Vec<Value> = fields
+                .iter()
+                .take(25)
+                .map(|(label, value)| {
+                    serde_json::json!({
+                        "name": truncate_chars(label, 256),
+                        "value": truncate_chars(value, 1024),
+                        "inline": true,
+                    })
+                })
+                .collect();
+            let mut embed = serde_json::json!({
+                "title": truncate_chars(&title, 256),
+                "description": truncate_chars(&text, 4096),
+                "fields": embed_fields,
+                "footer": { "text": truncate_chars(&event.event_type, 2048) },
+                "timestamp": Utc::now().to_rfc3339(),
+            });
+            if let Some(color) = color.and_then(|color| u32::from_str_radix(color, 16).ok()) {
+                embed["color"] = Value::from(color);
+            }
+            serde_json::json!({ "embeds": [embed] })
+        }
+    }
+}
+
+// Longest Retry-After converted as asked; larger, infinite or NaN values are
+// treated as this, which any sensible max_retry_wait refuses
+const MAX_RETRY_AFTER_SECS: f64 = 86_400.0;
+
+// How long a 429 response asks us to wait: the Retry-After header, or
+// Discord's `retry_after` body field, in seconds
+async fn retry_after(response: reqwest::Response) -> Duration {
+    let header = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok());
+    let seconds = match header {
+        Some(seconds) => Some(seconds),
+        None => response
+            .json::<Value>()
+            .await
+            .ok()
+            .and_then(|body| body.get("retry_after").and_then(Value::as_f64)),
+    };
+
+    let seconds = seconds.unwrap_or(1.0);
+    if seconds.is_nan() {
+        return Duration::from_secs_f64(MAX_RETRY_AFTER_SECS);
+    }
+    Duration::from_secs_f64(seconds.max(0.0).min(MAX_RETRY_AFTER_SECS))
+}
+
+// Chat Channel: POSTs the event, formatted for Slack, Teams or Discord, to an
+// incoming webhook
+struct ChatChannel {
+    config: ChatChannelConfig,
+    client: reqwest::Client,
+}
+
+impl ChatChannel {
+    fn new(config: ChatChannelConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .expect("Failed to build chat HTTP client");
+        ChatChannel { config, client }
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for ChatChannel {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn capabilities(&self) -> ChannelCapabilities {
+        // Messages show event content
+        ChannelCapabilities {
+            requires_plaintext: true,
+            requires_recipient: false,
+        }
+    }
+
+    async fn send(&self, delivery: &Delivery<'_>) -> Result<String, ChannelError> {
+        let url = delivery
+            .credentials
+            .and_then(|c| c.get("webhook_url"))
+            .or(self.config.webhook_url.as_ref())
+            .ok_or_else(|| ChannelError::Misconfigured("no webhook_url configured".to_string()))?;
+        let formatting = self
+            .config
+            .formatting
+            .get(&delivery.event.event_type)
+            .unwrap_or(&self.config.default_formatting);
+        let payload = chat_payload(self.config.format, formatting, delivery.event);
+
+        // Only rate limiting is retried; chat services answer other errors consistently
+        let attempts = self.config.max_attempts.max(1);
+        for attempt in 1..=attempts {
+            let response = self
+                .client
+                .post(url)
+                .json(&payload)
+                .send()
+                .await
+                .map_err(|e| ChannelError::Transport(e.to_string()))?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(format!("{} accepted", status));
+            }
+            if status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                let body = response.text().await.unwrap_or_default();
+                return Err(ChannelError::Rejected(format!("{}: {}", status, truncate_chars(&body, 200))));
+            }
+
+            let wait = retry_after(response).await;
+            if attempt == attempts || wait > self.config.max_retry_wait {
+                return Err(ChannelError::Rejected(format!("rate limited, retry after {:?}", wait)));
+            }
+            tokio::time::sleep(wait).await;
+        }
+
+        unreachable!("The final attempt always returns")
+    }
+
+    async fn health(&self) -> Result<(), ChannelError> {
+        // Incoming webhooks have no side-effect-free probe, so only the setup is checked
+        match &self.config.webhook_url {
+            Some(url) => reqwest::Url::parse(url)
+                .map(|_| ())
+                .map_err(|e| ChannelError::Misconfigured(format!("invalid webhook_url: {}", e))),
+            None => Ok(()),
+        }
+    }
+}
+
+// How the SMS provider authenticates us. Values may contain provider placeholders.
+#[derive(Clone)]
+enum SmsAuth {
+    None,
+    Basic { username: String, password: String },
+    Bearer { token: String },
+    Header { name: String, value: String },
+}
+
+// How the request body is encoded
+#[derive(Clone, Copy, Debug)]
+enum SmsBodyEncoding {
+    Form,
+    Json,
+}
+
+// When a provider response counts as accepted
+#[derive(Clone)]
+struct SmsSuccess {
+    // Accepted statuses; empty means any 2xx
+    statuses: Vec<u16>,
+    // Dotted path into the JSON response that must be present and non-null
+    json_path: Option<String>,
+    // Value the field at `json_path` must have, if any
+    expected: Option<String>,
+}
+
+// SMS provider described as an HTTP request template. `url`, auth, headers and
+// field values may use `{{to}}`, `{{from}}`, `{{body}}` and any provider value
+// or credential, e.g. `{{account_sid}}`.
+#[derive(Clone)]
+struct SmsProviderTemplate {
+    url: String,
+    auth: SmsAuth,
+    encoding: SmsBodyEncoding,
+    headers: Vec<(String, String)>,
+    // Body field name -> value template
+    fields: Vec<(String, String)>,
+    success: SmsSuccess,
+    // Dotted path to the provider's message id in the response
+    receipt_path: Option<String>,
+}
+
+// SMS channel settings. Tenants may override `from`, `to` (comma-separated
+// E.164 numbers) and any provider value in their "sms" channel credentials.
+#[derive(Clone)]
+struct SmsConfig {
+    provider: SmsProviderTemplate,
+    // Values available to the provider template, e.g. an account id
+    provider_values: HashMap<String, String>,
+    from: String,
+    to: Vec<String>,
+    timeout: Duration,
+    // Event type -> message template, rendered like EmailTemplate
+    templates: HashMap<String, String>,
+    // Message template for event types without one
+    default_template: String,
+    // Longer messages are cut to this many segments
+    max_segments: usize,
+}
+
+// Characters of the GSM 03.38 default alphabet, one septet each
+const GSM7_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+                          ¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+// Characters of the GSM extension table, two septets each
+const GSM7_EXTENDED: &str = "^{}\\[~]|€\u{c}";
+
+// Whether `number` is an E.164 number: `+`, a non-zero digit, at most 15 digits
+fn is_e164(number: &str) -> bool {
+    match number.strip_prefix('+') {
+        Some(digits) => {
+            (2..=15).contains(&digits.len())
+                && !digits.starts_with('0')
+                && digits.bytes().all(|b| b.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+// Split a message into SMS-sized segments. Messages that fit one SMS are sent
+// as is; longer ones are split into separately sent messages numbered
+// "(i/n) ", cut to `max_segments`. GSM-7 messages allow 160 septets, others
+// 70 UTF-16 code units.
+fn sms_segments(body: &str, max_segments: usize) -> Vec<String> {
+    let gsm7 = body.chars().all(|c| GSM7_BASIC.contains(c) || GSM7_EXTENDED.contains(c));
+    let (limit, cost): (usize, fn(char) -> usize) = if gsm7 {
+        (160, |c| if GSM7_EXTENDED.contains(c) { 2 } else { 1 })
+    } else {
+        (70, char::len_utf16)
+    };
+
+    if body.chars().map(cost).sum::<usize>() <= limit {
+        return vec![body.to_string()];
+    }
+
+    // Leave room for the widest numbering prefix
+    let max_segments = max_segments.max(1);
+    let prefix_width = format!("({}/{}) ", max_segments, max_segments).len();
+    let mut segments = vec![String::new()];
+    let mut used = 0;
+    for c in body.chars() {
+        if used + cost(c) > limit - prefix_width {
+            if segments.len() == max_segments {
+                // Mark the cut, dropping characters to make room for the marker
+                let marker = if gsm7 { "..." } else { "…" };
+                let last = segments.last_mut().expect("At least one segment");
+                while used + marker.chars().map(cost).sum::<usize>() > limit - prefix_width {
+                    match last.pop() {
+                        Some(c) => used -= cost(c),
+                        None => break,
+                    }
+                }
+                last.push_str(marker);
+                break;
+            }
+            segments.push(String::new());
+            used = 0;
+        }
+        segments.last_mut().expect("At least one segment").push(c);
+        used += cost(c);
+    }
+
+    let count = segments.len();
+    segments
+        .into_iter()
+        .enumerate()
+        .map(|(i, segment)| format!("({}/{}) {}", i + 1, count, segment))
+        .collect()
+}
+
+// Replace `{{name}}` placeholders from `values`; unknown names are a misconfiguration.
+// `escape`, if given, is applied to every substituted value.
+fn fill_placeholders(
+    template: &str,
+    values: &HashMap<String, String>,
+    escape: Option<fn(&str) -> String>,
+) -> Result<String, ChannelError> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let end = rest[start + 2..]
+            .find("}}")
+            .ok_or_else(|| ChannelError::Misconfigured(format!("unterminated placeholder in {}", template)))?;
+        let name = rest[start + 2..start + 2 + end].trim();
+        let value = values
+            .get(name)
+            .ok_or_else(|| ChannelError::Misconfigured(format!("unknown placeholder {}", name)))?;
+        match escape {
+            Some(escape) => filled.push_str(&escape(value)),
+            None => filled.push_str(value),
+        }
+        rest = &rest[start + 2 + end + 2..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+// Percent-encode everything but unreserved characters, so a value substituted
+// into a URL stays within its path segment or query parameter
+fn percent_encode_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Whether a provider response means the message was accepted
+fn sms_accepted(success: &SmsSuccess, status: reqwest::StatusCode, json: Option<&Value>) -> bool {
+    let status_ok = if success.statuses.is_empty() {
+        status.is_success()
+    } else {
+        success.statuses.contains(&status.as_u16())
+    };
+    let body_ok = match &success.json_path {
+        None => true,
+        Some(path) => match json.and_then(|json| json_at(json, path)) {
+            None | Some(Value::Null) => false,
+            Some(value) => match &success.expected {
+                None => true,
+                Some(expected) => value.as_str().map_or(value.to_string(), str::to_string) == *expected,
+            },
+        },
+    };
+    status_ok && body_ok
+}
+
+// SMS Channel: sends the rendered event to each number through an HTTP SMS
+// provider described by an SmsProviderTemplate
+struct SmsChannel {
+    config: SmsConfig,
+    client: reqwest::Client,
+}
+
+impl SmsChannel {
+    fn new(config: SmsConfig) -> Self {
+        SmsChannel {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    // Send one segment to one number, returning the provider's message id
+    async fn send_segment(&self, values: &HashMap<String, String>) -> Result<String, ChannelError> {
+        let provider = &self.config.provider;
+        // Values such as credentials may hold `/`, `?` or `#`, which must not reshape the URL
+        let url = fill_placeholders(&provider.url, values, Some(percent_encode_component))?;
+        let mut request = self.client.post(&url).timeout(self.config.timeout);
+
+        request = match &provider.auth {
+            SmsAuth::None => request,
+            SmsAuth::Basic { username, password } => request.basic_auth(
+                fill_placeholders(username, values, None)?,
+                Some(fill_placeholders(password, values, None)?),
+            ),
+            SmsAuth::Bearer { token } => request.bearer_auth(fill_placeholders(token, values, None)?),
+            SmsAuth::Header { name, value } => request.header(name.as_str(), fill_placeholders(value, values, None)?),
+        };
+        for (name, value) in &provider.headers {
+            request = request.header(name.as_str(), fill_placeholders(value, values, None)?);
+        }
+
+        let mut fields = Vec::with_capacity(provider.fields.len());
+        for (name, value) in &provider.fields {
+            fields.push((name.clone(), fill_placeholders(value, values, None)?));
+        }
+        request = match provider.encoding {
+            SmsBodyEncoding::Form => request.form(&fields),
+            SmsBodyEncoding::Json => {
+                let body: serde_json::Map<String, Value> =
+                    fields.into_iter().map(|(name, value)| (name, Value::String(value))).collect();
+                request.json(&body)
+            }
+        };
+
+        let response = request.send().await.map_err(|e| ChannelError::Transport(e.to_string()))?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let json: Option<Value> = serde_json::from_str(&body).ok();
+
+        if !sms_accepted(&provider.success, status, json.as_ref()) {
+            return Err(ChannelError::Rejected(format!("{}: {}", status, truncate_chars(&body, 200))));
+        }
+
+        let receipt = provider
+            .receipt_path
+            .as_ref()
+            .and_then(|path| json.as_ref().and_then(|json| json_at(json, path)))
+            .map(|id| id.as_str().map_or(id.to_string(), str::to_string));
+        Ok(receipt.unwrap_or_else(|| status.to_string()))
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for SmsChannel {
+    fn name(&self) -> &str {
+        "sms"
+    }
+
+    fn capabilities(&self) -> ChannelCapabilities {
+        // Messages show event content
+        ChannelCapabilities {
+            requires_plaintext: true,
+            requires_recipient: false,
+        }
+    }
+
+    async fn send(&self, delivery: &Delivery<'_>) -> Result<String, ChannelError> {
+        let mut values = self.config.provider_values.clone();
+        if let Some(credentials) = delivery.credentials {
+            values.extend(credentials.iter().map(|(name, value)| (name.clone(), value.clone())));
+        }
+        values.entry("from".to_string()).or_insert_with(|| self.config.from.clone());
+
+        let to: Vec<String> = match values.get("to") {
+            Some(to) => to.split(',').map(|to| to.trim().to_string()).filter(|to| !to.is_empty()).collect(),
+            None => self.config.to.clone(),
+        };
+        if to.is_empty() {
+            return Err(ChannelError::Misconfigured("no SMS recipients configured".to_string()));
+        }
+        if let Some(invalid) = to.iter().find(|number| !is_e164(number)) {
+            return Err(ChannelError::Misconfigured(format!("{} is not an E.164 number", invalid)));
+        }
+
+        let template = self
+            .config
+            .templates
+            .get(&delivery.event.event_type)
+            .unwrap_or(&self.config.default_template);
+        let segments = sms_segments(&render_template(template, delivery.event, None), self.config.max_segments);
+
+        // Numbers are sent to concurrently, each number's segments in order
+        let sends = to.iter().map(|number| {
+            let mut values = values.clone();
+            values.insert("to".to_string(), number.clone());
+            let segments = &segments;
+            async move {
+                let mut receipts = Vec::with_capacity(segments.len());
+                for segment in segments {
+                    values.insert("body".to_string(), segment.clone());
+                    receipts.push(self.send_segment(&values).await?);
+                }
+                Ok::<_, ChannelError>(receipts.join(","))
+            }
+        });
+        let results = join_all(sends).await;
+
+        let mut receipts = Vec::new();
+        let mut failures = Vec::new();
+        for (number, result) in to.iter().zip(results) {
+            match result {
+                Ok(receipt) => receipts.push(format!("{}: {}", number, receipt)),
+                Err(e) => failures.push(format!("{}: {}", number, e)),
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(receipts.join("; "))
+        } else {
+            Err(ChannelError::Rejected(format!(
+                "{} of {} number(s) failed: {}",
+                failures.len(),
+                to.len(),
+                failures.join("; ")
+            )))
+        }
+    }
+
+    async fn health(&self) -> Result<(), ChannelError> {
+        // Sending is the only probe most providers offer, so only the setup is checked
+        match self.config.to.iter().find(|number| !is_e164(number)) {
+            Some(invalid) => Err(ChannelError::Misconfigured(format!("{} is not an E.164 number", invalid))),
+            None => Ok(()),
+        }
+    }
+}
+
+// Request body for registering a webhook endpoint
+#[derive(Deserialize)]
+struct RegisterWebhook {
+    url: String,
+    #[serde(default)]
+    event_types: Vec<String>,
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+}
+
+// Register a webhook endpoint; its signing secret appears only in this response
+async fn register_webhook(
+    req: web::HttpRequest,
+    body: web::Bytes,
+    db_pool: web::Data<Option<PgPool>>,
+    auth: web::Data<Authenticator>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let principal = match authenticate_request(&req, &body, &auth).await {
+        Ok(principal) => principal,
+        Err(e) => return e.response(),
+    };
+    if let Err(response) = require_scope(&principal, SCOPE_WEBHOOKS) {
+        return response;
+    }
+    // Recipient keys and webhooks live only in Postgres
+    let db_pool = match db_pool.get_ref() {
+        Some(db_pool) => db_pool,
+        None => return storage_disabled(),
+    };
+
+    let body: RegisterWebhook = match parse_json_body(&body) {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    // Only https URLs whose host is reachable from the internet
+    if let Err(e) = resolve_webhook_target(&body.url, config.webhooks.allow_private_targets).await {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": e }));
+    }
+
+    // Between 1ms and the configured maximum; 0 would time out every delivery
+    let timeout = body
+        .timeout_ms
+        .map_or(config.webhooks.default_timeout, Duration::from_millis);
+    if timeout == Duration::ZERO || timeout > config.webhooks.max_timeout {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("timeout_ms must be between 1 and {}", config.webhooks.max_timeout.as_millis())
+        }));
+    }
+
+    // Generate the endpoint's HMAC secret and store it wrapped under the tenant's key
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let wrapped = match auth.tenant_keys.for_tenant(&principal.tenant).wrap_key(&secret) {
+        Ok(wrapped) => wrapped,
+        Err(e) => {
+            eprintln!("Failed to wrap webhook secret: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": "key_provider_error" }));
+        }
+    };
+
+    let endpoint =
+        match register_webhook_endpoint(db_pool, &principal.tenant, &body.url, &body.event_types, timeout, &wrapped).await {
+            Ok(endpoint) => endpoint,
+            Err(e) => return storage_error("Failed to register webhook endpoint", e),
+        };
+
+    auth.audit.record(AuditEntry {
+        principal: Some(principal.subject),
+        outcome: "success".to_string(),
+        details: serde_json::json!({ "webhook_id": endpoint.id, "url": endpoint.url }),
+        ..AuditEntry::for_request(&req, "webhook.register")
+    });
+
+    let mut response = endpoint.to_json();
+    response["secret"] = Value::String(base64::encode(secret));
+    HttpResponse::Created().json(response)
+}
+
+// List the tenant's webhook endpoints, including revoked ones
+async fn get_webhooks(req: web::HttpRequest, db_pool: web::Data<Option<PgPool>>, auth: web::Data<Authenticator>) -> HttpResponse {
+    let principal = match authenticate_request(&req, &[], &auth).await {
+        Ok(principal) => principal,
+        Err(e) => return e.response(),
+    };
+    if let Err(response) = require_scope(&principal, SCOPE_WEBHOOKS) {
+        return response;
+    }
+    // Recipient keys and webhooks live only in Postgres
+    let db_pool = match db_pool.get_ref() {
+        Some(db_pool) => db_pool,
+        None => return storage_disabled(),
+    };
+
+    let endpoints = match list_webhook_endpoints(db_pool, &principal.tenant).await {
+        Ok(endpoints) => endpoints,
+        Err(e) => return storage_error("Failed to list webhook endpoints", e),
+    };
+
+    HttpResponse::Ok().json(endpoints.iter().map(WebhookEndpoint::to_json).collect::<Vec<_>>())
+}
+
+// Revoke a webhook endpoint
+async fn delete_webhook(
+    req: web::HttpRequest,
+    webhook_id: web::Path<Uuid>,
+    db_pool: web::Data<Option<PgPool>>,
+    auth: web::Data<Authenticator>,
+) -> HttpResponse {
+    let principal = match authenticate_request(&req, &[], &auth).await {
+        Ok(principal) => principal,
+        Err(e) => return e.response(),
+    };
+    if let Err(response) = require_scope(&principal, SCOPE_WEBHOOKS) {
+        return response;
+    }
+    // Recipient keys and webhooks live only in Postgres
+    let db_pool = match db_pool.get_ref() {
+        Some(db_pool) => db_pool,
+        None => return storage_disabled(),
+    };
+
+    let webhook_id = webhook_id.into_inner();
+    let revoked = match revoke_webhook_endpoint(db_pool, &principal.tenant, webhook_id).await {
+        Ok(revoked) => revoked,
+        Err(e) => return storage_error("Failed to revoke webhook endpoint", e),
+    };
+
+    auth.audit.record(AuditEntry {
+        principal: Some(principal.subject),
+        outcome: if revoked { "success" } else { "not_found" }.to_string(),
+        details: serde_json::json!({ "webhook_id": webhook_id }),
+        ..AuditEntry::for_request(&req, "webhook.revoke")
+    });
+
+    if revoked {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+// Event struct
+#[derive(Clone, Serialize, Deserialize)]
+struct Event {
+    id: Uuid,
+    event_type: String,
+    // Recipient id; notifications are sealed to the recipient's key if one is registered
+    #[serde(default)]
+    recipient: Option<String>,
+    data: Value,
+}
+
+// Encrypted Event struct
+#[derive(Serialize, Deserialize)]
+struct EncryptedEvent {
+    id: Uuid,
+    tenant_id: String,
+    event_type: String,
+    recipient: Option<String>,
+    algorithm: String,
+    // Per-event data key, wrapped by a key-encryption key
+    wrapped_key: WrappedKey,
+    // Event data with every encrypted field replaced by null
+    data: Value,
+    encrypted_fields: Vec<EncryptedField>,
+}
+
+// A single encrypted field of an EncryptedEvent
+#[derive(Serialize, Deserialize)]
+struct EncryptedField {
+    // Concrete JSON path of the field, e.g. `$.items[0].sku`
+    path: String,
+    nonce: Vec<u8>,
+    // AES-GCM ciphertext of the field's JSON value, tag appended
+    ciphertext: Vec<u8>,
+}
+
+// Shared database pool options from Config
+fn db_pool_options(config: &Config) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.db_max_connections)
+        // In sqlx 0.5 this bounds how long acquiring a pooled connection may take
+        .connect_timeout(config.db_acquire_timeout)
+        // Ping connections before handing them out so dead ones are replaced
+        .test_before_acquire(true)
+}
+
+// Build the shared database pool, failing fast if Postgres is unreachable
+async fn connect_db_pool(config: &Config) -> Result<PgPool, sqlx::Error> {
+    db_pool_options(config).connect(&config.db_url).await
+}
+
+// Error text from a failed health check names hosts, relays and driver
+// errors, so only callers authenticated with keys:admin get it; everyone else
+// sees the status alone. Anonymous probes skip authentication entirely so
+// they don't fill the audit log with failures.
+async fn shows_health_details(req: &web::HttpRequest, auth: &Authenticator) -> bool {
+    let presents_credentials =
+        req.headers().contains_key("API-KEY") || req.headers().contains_key(REQUEST_SIGNATURE_HEADER);
+    presents_credentials && authenticate_admin(req, &[], auth).await.is_ok()
+}
+
+// Health check: reports whether the database is reachable through the pool,
+// and how many audit records were lost. Without Postgres there is nothing to
+// check, and the service runs as configured.
+async fn health_check(req: web::HttpRequest, db_pool: web::Data<Option<PgPool>>, auth: web::Data<Authenticator>) -> HttpResponse {
+    let audit_records_lost = auth.audit.lost();
+    let db_pool = match db_pool.get_ref() {
+        Some(db_pool) => db_pool,
+        None => {
+            return HttpResponse::Ok().json(serde_json::json!({
+                "status": "ok",
+                "storage": "disabled",
+                "audit_records_lost": audit_records_lost,
+            }))
+        }
+    };
+    match sqlx::query("SELECT 1").execute(db_pool).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "status": "ok", "audit_records_lost": audit_records_lost })),
+        Err(e) => {
+            eprintln!("Health check failed: {}", e);
+            if shows_health_details(&req, &auth).await {
+                HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                    "status": "unavailable",
+                    "error": e.to_string(),
+                }))
+            } else {
+                HttpResponse::ServiceUnavailable().json(serde_json::json!({ "status": "unavailable" }))
+            }
+        }
+    }
+}
+
+// Database schema, applied at startup
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS api_keys (
+        prefix TEXT PRIMARY KEY,
+        key_hash BYTEA NOT NULL,
+        scopes TEXT[] NOT NULL DEFAULT '{}',
+        issued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        expires_at TIMESTAMPTZ,
+        revoked_at TIMESTAMPTZ,
+        last_used_at TIMESTAMPTZ,
+        signing_key JSONB,
+        rate_limits JSONB NOT NULL DEFAULT '{}',
+        tenant_id TEXT NOT NULL DEFAULT 'default'
+    )",
+    // Upgrade tables created by earlier versions, which CREATE TABLE IF NOT EXISTS leaves alone
+    "ALTER TABLE api_keys
+        ADD COLUMN IF NOT EXISTS prefix TEXT,
+        ADD COLUMN IF NOT EXISTS key_hash BYTEA,
+        ADD COLUMN IF NOT EXISTS scopes TEXT[] NOT NULL DEFAULT '{}',
+        ADD COLUMN IF NOT EXISTS issued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ,
+        ADD COLUMN IF NOT EXISTS revoked_at TIMESTAMPTZ,
+        ADD COLUMN IF NOT EXISTS last_used_at TIMESTAMPTZ,
+        ADD COLUMN IF NOT EXISTS signing_key JSONB,
+        ADD COLUMN IF NOT EXISTS rate_limits JSONB NOT NULL DEFAULT '{}',
+        ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default'",
+    // Hash plaintext keys from the original `api_keys (key)` table and drop them.
+    // They keep working under a derived prefix (see legacy_key_prefix) with the
+    // only permission they had before scopes existed.
+    "DO $$
+    BEGIN
+        IF EXISTS (SELECT 1 FROM information_schema.columns
+                   WHERE table_schema = current_schema() AND table_name = 'api_keys' AND column_name = 'key') THEN
+            UPDATE api_keys SET
+                prefix = 'legacy-' || substr(encode(sha256(convert_to(key, 'UTF8')), 'hex'), 1, 16),
+                key_hash = sha256(convert_to(key, 'UTF8')),
+                scopes = ARRAY['notify:send']
+            WHERE prefix IS NULL;
+            ALTER TABLE api_keys DROP COLUMN key;
+        END IF;
+    END $$",
+    "ALTER TABLE api_keys ALTER COLUMN prefix SET NOT NULL, ALTER COLUMN key_hash SET NOT NULL",
+    "CREATE UNIQUE INDEX IF NOT EXISTS api_keys_prefix ON api_keys (prefix)",
+    // Events encrypted as a single blob, before per-field encryption, can't be
+    // converted without their keys; keep them aside in encrypted_events_v1
+    "DO $$
+    BEGIN
+        IF EXISTS (SELECT 1 FROM information_schema.columns
+                   WHERE table_schema = current_schema() AND table_name = 'encrypted_events' AND column_name = 'nonce') THEN
+            ALTER TABLE encrypted_events RENAME TO encrypted_events_v1;
+            ALTER INDEX encrypted_events_pkey RENAME TO encrypted_events_v1_pkey;
+            ALTER INDEX IF EXISTS encrypted_events_kek_id RENAME TO encrypted_events_v1_kek_id;
+        END IF;
+    END $$",
+    "CREATE TABLE IF NOT EXISTS encrypted_events (
+        id UUID NOT NULL,
+        tenant_id TEXT NOT NULL DEFAULT 'default',
+        event_type TEXT NOT NULL,
+        recipient_id TEXT,
+        algorithm TEXT NOT NULL,
+        kek_id TEXT,
+        wrapped_key JSONB NOT NULL,
+        data JSONB NOT NULL,
+        encrypted_fields JSONB NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        -- Event ids come from clients, so they are only unique within a tenant
+        PRIMARY KEY (tenant_id, id)
+    )",
+    "ALTER TABLE encrypted_events
+        ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default',
+        ADD COLUMN IF NOT EXISTS recipient_id TEXT,
+        ALTER COLUMN kek_id DROP NOT NULL",
+    // Tables keyed by id alone let one tenant's event ids collide with another's
+    "DO $$
+    BEGIN
+        IF NOT EXISTS (SELECT 1 FROM information_schema.key_column_usage
+                       WHERE table_schema = current_schema() AND table_name = 'encrypted_events'
+                         AND constraint_name = 'encrypted_events_pkey' AND column_name = 'tenant_id') THEN
+            ALTER TABLE encrypted_events DROP CONSTRAINT encrypted_events_pkey;
+            ALTER TABLE encrypted_events ADD PRIMARY KEY (tenant_id, id);
+        END IF;
+    END $$",
+    "CREATE INDEX IF NOT EXISTS encrypted_events_kek_id ON encrypted_events (kek_id, id)",
+    "CREATE TABLE IF NOT EXISTS key_rotation_jobs (
+        id UUID PRIMARY KEY,
+        target_key_id TEXT NOT NULL,
+        -- NULL for the shared key-encryption keys
+        tenant_id TEXT,
+        last_event_id UUID,
+        processed BIGINT NOT NULL DEFAULT 0,
+        failed BIGINT NOT NULL DEFAULT 0,
+        started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        finished_at TIMESTAMPTZ
+    )",
+    "CREATE TABLE IF NOT EXISTS recipient_keys (
+        id UUID PRIMARY KEY,
+        tenant_id TEXT NOT NULL DEFAULT 'default',
+        recipient_id TEXT NOT NULL,
+        public_key BYTEA NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        revoked_at TIMESTAMPTZ
+    )",
+    "ALTER TABLE key_rotation_jobs ADD COLUMN IF NOT EXISTS tenant_id TEXT",
+    "ALTER TABLE recipient_keys ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default'",
+    // The index once covered (recipient_id, created_at) only
+    "DROP INDEX IF EXISTS recipient_keys_recipient_id",
+    "CREATE INDEX IF NOT EXISTS recipient_keys_tenant_recipient_id ON recipient_keys (tenant_id, recipient_id, created_at)",
+    "CREATE TABLE IF NOT EXISTS webhook_endpoints (
+        id UUID PRIMARY KEY,
+        tenant_id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        event_types TEXT[] NOT NULL DEFAULT '{}',
+        timeout_ms BIGINT NOT NULL,
+        secret JSONB NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        revoked_at TIMESTAMPTZ
+    )",
+    "CREATE INDEX IF NOT EXISTS webhook_endpoints_tenant_id ON webhook_endpoints (tenant_id, created_at)",
+    "CREATE TABLE IF NOT EXISTS audit_log (
+        seq BIGSERIAL PRIMARY KEY,
+        id UUID NOT NULL,
+        at TIMESTAMPTZ NOT NULL,
+        request_id TEXT,
+        action TEXT NOT NULL,
+        principal TEXT,
+        event_id UUID,
+        outcome TEXT NOT NULL,
+        details JSONB NOT NULL,
+        prev_hash TEXT NOT NULL,
+        hash TEXT NOT NULL
+    )",
+    // Append-only: updates and deletes are silently discarded
+    "CREATE OR REPLACE RULE audit_log_no_update AS ON UPDATE TO audit_log DO INSTEAD NOTHING",
+    "CREATE OR REPLACE RULE audit_log_no_delete AS ON DELETE TO audit_log DO INSTEAD NOTHING",
+];
+
+async fn apply_schema(pool: &PgPool) -> Result<(), sqlx::Error> {
+    for statement in SCHEMA {
+        sqlx::query(statement).execute(pool).await?;
+    }
+    Ok(())
+}
+
+// Header carrying the request id, accepted from the caller or generated
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Request id assigned to every request, as stored in its extensions
+#[derive(Clone)]
+struct RequestId(String);
+
+// Use the caller's X-Request-Id if it is sane, else generate one
+fn assign_request_id(req: &ServiceRequest) -> String {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+    request_id
+}
+
+// An action to record, before it is chained
+struct AuditEntry {
+    request_id: Option<String>,
+    action: &'static str,
+    // Subject of the principal that acted, if known
+    principal: Option<String>,
+    event_id: Option<Uuid>,
+    outcome: String,
+    details: Value,
+}
+
+impl AuditEntry {
+    // Entry for an action taken while serving `req`
+    fn for_request(req: &web::HttpRequest, action: &'static str) -> Self {
+        AuditEntry {
+            request_id: req.extensions().get::<RequestId>().map(|RequestId(id)| id.clone()),
+            ..AuditEntry::new(action)
+        }
+    }
+
+    fn new(action: &'static str) -> Self {
+        AuditEntry {
+            request_id: None,
+            action,
+            principal: None,
+            event_id: None,
+            outcome: String::new(),
+            details: Value::Null,
+        }
+    }
+}
+
+// A chained audit record. Its hash covers every field here, including the
+// previous record's hash, so any edit, deletion or reordering breaks the chain.
+#[derive(Serialize, Deserialize)]
+struct AuditRecord {
+    id: Uuid,
+    // Truncated to microseconds so it survives a round trip through Postgres
+    at: DateTime<Utc>,
+    request_id: Option<String>,
+    action: String,
+    principal: Option<String>,
+    event_id: Option<Uuid>,
+    outcome: String,
+    details: Value,
+    prev_hash: String,
+}
+
+impl AuditRecord {
+    // Unchained record for an entry; prev_hash is filled in when it is appended
+    fn from_entry(entry: AuditEntry) -> Self {
+        AuditRecord {
+            id: Uuid::new_v4(),
+            at: Utc::now().trunc_subsecs(6),
+            request_id: entry.request_id,
+            action: entry.action.to_string(),
+            principal: entry.principal,
+            event_id: entry.event_id,
+            outcome: entry.outcome,
+            details: entry.details,
+            prev_hash: String::new(),
+        }
+    }
+
+    fn hash(&self) -> String {
+        let canonical = serde_json::to_vec(self).expect("Audit record serializes");
+        hex::encode(Sha256::digest(&canonical))
+    }
+
+    fn from_row(row: &PgRow) -> Self {
+        let action: String = row.get("action");
+        let Json(details) = row.get("details");
+
+        AuditRecord {
+            id: row.get("id"),
+            at: row.get("at"),
+            request_id: row.get("request_id"),
+            action,
+            principal: row.get("principal"),
+            event_id: row.get("event_id"),
+            outcome: row.get("outcome"),
+            details,
+            prev_hash: row.get("prev_hash"),
+        }
+    }
+}
+
+// prev_hash of the first record in a chain
+const AUDIT_GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// Audit Log: append-only record of authentication, key management and
+// notification actions. A writer task per process chains records onto the
+// newest stored one and appends them to Postgres and, optionally, a
+// JSON-lines file. Without Postgres the chain starts afresh in each process
+// and only the file keeps it.
+struct AuditLog {
+    sender: mpsc::Sender<AuditEntry>,
+    writer: tokio::task::JoinHandle<()>,
+    // Records dropped because the queue was full, or not stored or written out
+    lost: Arc<AtomicU64>,
+}
+
+// Capacity of the audit queue. Records beyond it are dropped and counted,
+// rather than held in memory without limit while the database is slow.
+const AUDIT_QUEUE_SIZE: usize = 10_000;
+
+// Most records appended in one transaction
+const AUDIT_BATCH_SIZE: usize = 100;
+
+impl AuditLog {
+    fn start(db_pool: Option<PgPool>, file: Option<PathBuf>) -> Self {
+        let (sender, receiver) = mpsc::channel(AUDIT_QUEUE_SIZE);
+        let lost = Arc::new(AtomicU64::new(0));
+        let writer = tokio::spawn(run_audit_writer(receiver, db_pool, file, lost.clone()));
+        AuditLog { sender, writer, lost }
+    }
+
+    fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.sender.try_send(entry) {
+            self.lost.fetch_add(1, Ordering::Relaxed);
+            match e {
+                mpsc::error::TrySendError::Full(_) => eprintln!("Audit log queue full; record lost"),
+                mpsc::error::TrySendError::Closed(_) => eprintln!("Audit log writer stopped; record lost"),
+            }
+        }
+    }
+
+    // Records lost since startup, reported by /health
+    fn lost(&self) -> u64 {
+        self.lost.load(Ordering::Relaxed)
+    }
+
+    // Write out everything recorded so far and stop the writer
+    async fn close(self) {
+        drop(self.sender);
+        if let Err(e) = self.writer.await {
+            eprintln!("Audit log writer failed: {}", e);
+        }
+    }
+}
+
+// Advisory lock held while appending, so every process writing the audit log
+// (the server and CLI commands alike) extends the same chain
+const AUDIT_CHAIN_LOCK: i64 = 0x6175_6469_745f_6c6f;
+
+// Append queued entries in batches. Failures are logged and counted in `lost`;
+// the writer keeps running, so one bad write doesn't stop the audit log.
+async fn run_audit_writer(
+    mut receiver: mpsc::Receiver<AuditEntry>,
+    db_pool: Option<PgPool>,
+    path: Option<PathBuf>,
+    lost: Arc<AtomicU64>,
+) {
+    let mut file = path.and_then(|path| match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(file) => Some(file),
+        Err(e) => {
+            eprintln!("Failed to open audit log file {}, writing Postgres only: {}", path.display(), e);
+            None
+        }
+    });
+
+    // Chain head when there is no database to hold it
+    let mut local_head = AUDIT_GENESIS_HASH.to_string();
+
+    while let Some(entry) = receiver.recv().await {
+        // Take whatever else is already queued, up to a batch
+        let mut entries = vec![entry];
+        while entries.len() < AUDIT_BATCH_SIZE {
+            match receiver.try_recv() {
+                Ok(entry) => entries.push(entry),
+                Err(_) => break,
+            }
+        }
+
+        let appended = match &db_pool {
+            Some(db_pool) => append_audit_records(db_pool, entries).await,
+            None => Ok(entries
+                .into_iter()
+                .map(|entry| {
+                    let mut record = AuditRecord::from_entry(entry);
+                    record.prev_hash = local_head.clone();
+                    local_head = record.hash();
+                    (record, local_head.clone())
+                })
+                .collect()),
+        };
+
+        match appended {
+            Ok(records) => {
+                if let Some(file) = &mut file {
+                    let mut lines = String::new();
+                    for (record, hash) in &records {
+                        let mut line = serde_json::to_value(record).expect("Audit record serializes");
+                        line["hash"] = Value::String(hash.clone());
+                        lines.push_str(&line.to_string());
+                        lines.push('\n');
+                    }
+                    if let Err(e) = file.write_all(lines.as_bytes()) {
+                        lost.fetch_add(records.len() as u64, Ordering::Relaxed);
+                        eprintln!("Failed to write {} record(s) to the audit log file: {}", records.len(), e);
+                    }
+                }
+            }
+            // The append rolled back, so the chain is intact; keep the entries in the process log
+            Err((e, records)) => {
+                lost.fetch_add(records.len() as u64, Ordering::Relaxed);
+                for record in records {
+                    eprintln!(
+                        "Failed to store audit record: {}: {}",
+                        e,
+                        serde_json::to_string(&record).expect("Audit record serializes")
+                    );
+                }
+            }
+        }
+    }
+}
+
+// Chain entries onto the newest stored record and insert them, in one
+// transaction under AUDIT_CHAIN_LOCK. On failure the unchained records are returned.
+async fn append_audit_records(
+    db_pool: &PgPool,
+    entries: Vec<AuditEntry>,
+) -> Result<Vec<(AuditRecord, String)>, (sqlx::Error, Vec<AuditRecord>)> {
+    let mut records: Vec<AuditRecord> = entries.into_iter().map(AuditRecord::from_entry).collect();
+
+    let result = async {
+        let mut tx = db_pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(AUDIT_CHAIN_LOCK)
+            .execute(&mut tx)
+            .await?;
+        let head: Option<String> = sqlx::query_scalar("SELECT hash FROM audit_log ORDER BY seq DESC LIMIT 1")
+            .fetch_optional(&mut tx)
+            .await?;
+
+        let mut prev_hash = head.unwrap_or_else(|| AUDIT_GENESIS_HASH.to_string());
+        let mut hashes = Vec::with_capacity(records.len());
+        for record in &mut records {
+            record.prev_hash = prev_hash;
+            let hash = record.hash();
+            sqlx::query(
+                "INSERT INTO audit_log (id, at, request_id, action, principal, event_id, outcome, details, prev_hash, hash)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            )
+            .bind(record.id)
+            .bind(record.at)
+            .bind(&record.request_id)
+            .bind(&record.action)
+            .bind(&record.principal)
+            .bind(record.event_id)
+            .bind(&record.outcome)
+            .bind(Json(&record.details))
+            .bind(&record.prev_hash)
+            .bind(&hash)
+            .execute(&mut tx)
+            .await?;
+
+            prev_hash = hash.clone();
+            hashes.push(hash);
+        }
+
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(hashes)
+    }
+    .await;
+
+    match result {
+        Ok(hashes) => Ok(records.into_iter().zip(hashes).collect()),
+        Err(e) => {
+            for record in &mut records {
+                record.prev_hash.clear();
+            }
+            Err((e, records))
+        }
+    }
+}
+
+// Records checked per query when verifying the chain
+const AUDIT_VERIFY_BATCH_SIZE: i64 = 1000;
+
+// Walk the stored chain, returning the number of records checked or the
+// sequence number of the first record that doesn't match
+async fn verify_audit_chain(db_pool: &PgPool) -> Result<Result<u64, i64>, sqlx::Error> {
+    let mut prev_hash = AUDIT_GENESIS_HASH.to_string();
+    let mut last_seq = 0i64;
+    let mut checked = 0u64;
+
+    loop {
+        let rows = sqlx::query("SELECT * FROM audit_log WHERE seq > $1 ORDER BY seq LIMIT $2")
+            .bind(last_seq)
+            .bind(AUDIT_VERIFY_BATCH_SIZE)
+            .fetch_all(db_pool)
+            .await?;
+        if rows.is_empty() {
+            return Ok(Ok(checked));
+        }
+
+        for row in &rows {
+            last_seq = row.get("seq");
+            let record = AuditRecord::from_row(row);
+            let hash: String = row.get("hash");
+            if record.prev_hash != prev_hash || record.hash() != hash {
+                return Ok(Err(last_seq));
+            }
+            prev_hash = hash;
+            checked += 1;
+        }
+    }
+}
+
+// TLS settings for the HttpServer
+#[derive(Clone)]
+struct TlsConfig {
+    // PEM certificate chain presented to clients
+    cert_file: String,
+    // PEM PKCS#8 private key for `cert_file`
+    key_file: String,
+    // PEM CA bundle used to verify client certificates; None disables them
+    client_ca_file: Option<String>,
+    // Refuse connections without a valid client certificate
+    require_client_cert: bool,
+    // Client certificate identity (SAN or subject CN) -> tenant and granted scopes
+    client_identities: HashMap<String, ClientIdentity>,
+}
+
+// What a client certificate identity is allowed to do
+#[derive(Clone)]
+struct ClientIdentity {
+    tenant: String,
+    scopes: Vec<String>,
+}
+
+// Identities of a verified client certificate, captured per connection
+#[derive(Clone)]
+struct ClientCertificate {
+    // DNS, URI and email SANs, then the subject CN
+    identities: Vec<String>,
+}
+
+// Build the rustls server configuration from Config
+fn tls_server_config(tls: &TlsConfig) -> std::io::Result<ServerConfig> {
+    let read_pem = |path: &str| File::open(path).map(BufReader::new);
+    let invalid = |what: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, what.to_string());
+
+    // Client certificates are checked against the CA bundle by rustls itself
+    let client_verifier = match &tls.client_ca_file {
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for der in certs(&mut read_pem(ca_file)?).map_err(|_| invalid("invalid client CA bundle"))? {
+                roots
+                    .add(&Certificate(der))
+                    .map_err(|_| invalid("invalid client CA bundle"))?;
+            }
+            if roots.is_empty() {
+                return Err(invalid("no certificates in client CA bundle"));
+            }
+            if tls.require_client_cert {
+                AllowAnyAuthenticatedClient::new(roots)
+            } else {
+                AllowAnyAnonymousOrAuthenticatedClient::new(roots)
+            }
+        }
+        None => NoClientAuth::new(),
+    };
+
+    let cert_chain = certs(&mut read_pem(&tls.cert_file)?)
+        .map_err(|_| invalid("invalid TLS certificate"))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let mut keys = pkcs8_private_keys(&mut read_pem(&tls.key_file)?).map_err(|_| invalid("invalid TLS key"))?;
+    if keys.is_empty() {
+        return Err(invalid("no PKCS#8 private key in TLS key file"));
+    }
+
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(client_verifier)
+        .with_single_cert(cert_chain, PrivateKey(keys.remove(0)))
+        .map_err(|e| invalid(&e.to_string()))
+}
+
+// Capture the client certificate of a TLS connection, available to handlers
+// through `conn_data`. rustls has already verified it against the CA bundle
+// by the time the connection is handed over.
+fn client_certificate(conn: &dyn Any, data: &mut Extensions) {
+    if let Some(cert) = conn
+        .downcast_ref::<TlsStream<TcpStream>>()
+        .and_then(|stream| peer_certificate_identities(stream.get_ref().1.peer_certificates()?.first()?))
+    {
+        data.insert(cert);
+    }
+}
+
+// Identities named by a DER-encoded certificate
+fn peer_certificate_identities(der: &Certificate) -> Option<ClientCertificate> {
+    let (_, cert) = X509Certificate::from_der(&der.0).ok()?;
+
+    let mut identities = Vec::new();
+    if let Ok(Some(san)) = cert.subject_alternative_name() {
+        for name in &san.value.general_names {
+            match name {
+                GeneralName::DNSName(name) | GeneralName::URI(name) | GeneralName::RFC822Name(name) => {
+                    identities.push(name.to_string())
+                }
+                _ => {}
+            }
+        }
+    }
+    if let Some(cn) = cert.subject().iter_common_name().next().and_then(|cn| cn.as_str().ok()) {
+        identities.push(cn.to_string());
+    }
+
+    Some(ClientCertificate { identities })
+}
+
+// Map a client certificate to the first of its identities listed in Config
+fn client_certificate_authenticator(
+    cert: &ClientCertificate,
+    client_identities: &HashMap<String, ClientIdentity>,
+) -> Result<Principal, AuthError> {
+    cert.identities
+        .iter()
+        .find_map(|identity| {
+            client_identities.get(identity).map(|client| Principal {
+                subject: identity.clone(),
+                tenant: client.tenant.clone(),
+                scopes: client.scopes.clone(),
+                rate_limits: RateLimits::default(),
+            })
+        })
+        .ok_or(AuthError::UnknownClientCertificate)
+}
+
+// Initialize API Gateway
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let config = Config {
+        api_key: "YOUR_API_KEY".to_string(),
+        db_url: "YOUR_DB_URL".to_string(),
+        key_store: KeyStoreKind::Postgres,
+        seed_keys: Vec::new(),
+        db_max_connections: 10,
+        db_acquire_timeout: Duration::from_secs(5),
+        key_file: "keys.json".to_string(),
+        signing_key_file: "signing_keys.json".to_string(),
+        field_encryption: HashMap::from([
+            (
+                "payment.completed".to_string(),
+                vec!["$.user.email".to_string(), "$.card.last4".to_string()],
+            ),
+            // Alerts carry no secrets, so their message can be sent by SMS and chat
+            ("system.alert".to_string(), Vec::new()),
+        ]),
+        default_field_encryption: vec!["$".to_string()],
+        jwt: None,
+        bind_address: "127.0.0.1:8080".to_string(),
+        tls: None,
+        default_rate_limits: RateLimits {
+            requests_per_second: Some(10.0),
+            burst: Some(20),
+            daily_quota: None,
+            monthly_quota: None,
+        },
+        ip_rate_limits: None,
+        auth_cache_ttl: Duration::from_secs(30),
+        auth_cache_capacity: 10_000,
+        rejected_key_cache_ttl: Duration::from_secs(5),
+        rejected_key_cache_capacity: 10_000,
+        lockout: LockoutPolicy {
+            window: Duration::from_secs(15 * 60),
+            delay_after: 3,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(5),
+            ban_after: 10,
+            ban_duration: Duration::from_secs(15 * 60),
+        },
+        audit_log_file: Some("audit.jsonl".to_string()),
+        tenants: HashMap::from([(DEFAULT_TENANT.to_string(), TenantConfig::default())]),
+        channel_routes: HashMap::new(),
+        default_channels: vec!["webhook".to_string()],
+        webhooks: WebhookConfig {
+            max_attempts: 3,
+            retry_backoff: Duration::from_secs(1),
+            default_timeout: Duration::from_secs(10),
+            max_timeout: Duration::from_secs(30),
+            delivery_deadline: Duration::from_secs(30),
+            allow_private_targets: false,
+        },
+        // A local sink such as MailHog; use StartTls or Tls with a real relay
+        email: Some(EmailConfig {
+            relay: "localhost".to_string(),
+            port: 1025,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "notifier@example.com".to_string(),
+            to: vec!["ops@example.com".to_string()],
+            timeout: Duration::from_secs(10),
+            templates: HashMap::from([(
+                "payment.completed".to_string(),
+                EmailTemplate {
+                    subject: "Payment {{data.payment_id}} completed".to_string(),
+                    text: "Payment {{data.payment_id}} of {{data.amount}} completed.".to_string(),
+                    html: Some("<p>Payment <b>{{data.payment_id}}</b> of {{data.amount}} completed.</p>".to_string()),
+                },
+            )]),
+            default_template: EmailTemplate {
+                subject: "Notification: {{event_type}}".to_string(),
+                text: "Event {{id}} ({{event_type}})".to_string(),
+                html: None,
+            },
+        }),
+        chat_channels: vec![ChatChannelConfig {
+            name: "slack".to_string(),
+            format: ChatFormat::Slack,
+            webhook_url: None,
+            formatting: HashMap::new(),
+            default_formatting: ChatFormatting {
+                title: "{{event_type}}".to_string(),
+                text: "Event `{{id}}`".to_string(),
+                fields: vec![ChatField {
+                    label: "Recipient".to_string(),
+                    path: "recipient".to_string(),
+                }],
+                color: None,
+            },
+            max_attempts: 3,
+            max_retry_wait: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+        }],
+        // Twilio's Messages API; point `url` at a local mock to test without the network.
+        // Tenants supply `account_sid` and `auth_token` as sms credentials.
+        sms: Some(SmsConfig {
+            provider: SmsProviderTemplate {
+                url: "https://api.twilio.com/2010-04-01/Accounts/{{account_sid}}/Messages.json".to_string(),
+                auth: SmsAuth::Basic {
+                    username: "{{account_sid}}".to_string(),
+                    password: "{{auth_token}}".to_string(),
+                },
+                encoding: SmsBodyEncoding::Form,
+                headers: Vec::new(),
+                fields: vec![
+                    ("To".to_string(), "{{to}}".to_string()),
+                    ("From".to_string(), "{{from}}".to_string()),
+                    ("Body".to_string(), "{{body}}".to_string()),
+                ],
+                success: SmsSuccess {
+                    statuses: vec![201],
+                    json_path: Some("sid".to_string()),
+                    expected: None,
+                },
+                receipt_path: Some("sid".to_string()),
+            },
+            provider_values: HashMap::new(),
+            from: "+15005550006".to_string(),
+            to: Vec::new(),
+            timeout: Duration::from_secs(10),
+            templates: HashMap::from([("system.alert".to_string(), "{{event_type}}: {{data.message}}".to_string())]),
+            // Other event types have their data encrypted, so only its envelope can be sent
+            default_template: "{{event_type}} notification {{id}}".to_string(),
+            max_segments: 3,
+        }),
+    };
+
+    // Parse field encryption policies up front so bad paths fail at startup
+    let field_policies = web::Data::new(
+        FieldEncryptionPolicies::from_config(&config.field_encryption, &config.default_field_encryption)
+            .expect("Invalid field encryption policy"),
+    );
+
+    // Load every tenant's key-encryption keys once at startup
+    let tenant_keys = Arc::new(TenantKeys::load(&config).expect("Failed to load key files"));
+
+    // Load notification signing keys
+    let signer = web::Data::new(
+        NotificationSigner::load(Path::new(&config.signing_key_file)).expect("Failed to load signing keys"),
+    );
+
+    // Shared database pool, used by every worker. With a non-Postgres key store the
+    // gateway must start without a database server, so connect lazily and run
+    // without one (no stored events, recipient keys or webhooks) if it is
+    // unreachable or db_url isn't a Postgres URL.
+    let db_pool = match config.key_store {
+        KeyStoreKind::Postgres => {
+            let db_pool = connect_db_pool(&config).await.expect("Failed to connect to database");
+            apply_schema(&db_pool).await.expect("Failed to apply database schema");
+            Some(db_pool)
+        }
+        _ => match db_pool_options(&config).connect_lazy(&config.db_url) {
+            Ok(db_pool) => match apply_schema(&db_pool).await {
+                Ok(()) => Some(db_pool),
+                Err(e) => {
+                    eprintln!("Postgres unavailable, event storage disabled: {}", e);
+                    None
+                }
+            },
+            Err(e) => {
+                eprintln!("Invalid database URL, event storage disabled: {}", e);
+                None
+            }
+        },
+    };
+    let event_store: Arc<dyn EventStore> = match &db_pool {
+        Some(db_pool) => Arc::new(PostgresEventStore { pool: db_pool.clone() }),
+        None => Arc::new(DiscardingEventStore),
+    };
+
+    // API key store
+    let key_store = open_key_store(&config, db_pool.as_ref()).await.expect("Failed to open key store");
+
+    // Audit log, also covering key management through the CLI below
+    let audit = AuditLog::start(db_pool.clone(), config.audit_log_file.as_ref().map(PathBuf::from));
+
+    // `verify-audit-log` checks the stored hash chain and exits
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("verify-audit-log") {
+        let db_pool = db_pool.as_ref().expect("verify-audit-log needs Postgres");
+        match verify_audit_chain(db_pool).await.expect("Failed to read audit log") {
+            Ok(checked) => println!("Audit log intact: {} records verified", checked),
+            Err(seq) => println!("Audit log chain broken at record {}", seq),
+        }
+        return Ok(());
+    }
+
+    // `rotate-key <new-key-id> [tenant]` activates a new key, re-encrypts stored events and exits.
+    // Without a tenant it rotates the shared keys.
+    if args.get(1).map(String::as_str) == Some("rotate-key") {
+        let new_key_id = args.get(2).expect("Usage: rotate-key <new-key-id> [tenant]");
+        let db_pool = db_pool.clone().expect("rotate-key needs Postgres");
+        let (keys, tenants) = tenant_keys
+            .providers()
+            .into_iter()
+            .find(|(_, tenants)| tenants.only.as_ref() == args.get(3))
+            .expect("Tenant has no key file of its own");
+        let tenant_id = tenants.only.clone();
+        let mut progress = rotate_encryption_key(keys, tenants, db_pool, new_key_id)
+            .expect("Failed to rotate encryption key");
+
+        while progress.changed().await.is_ok() {
+            let p = progress.borrow().clone();
+            println!(
+                "Re-encrypted {}/{} events to key {} ({} failed)",
+                p.processed, p.total, p.target_key_id, p.failed
+            );
+            if p.done {
+                break;
+            }
+        }
+
+        let p = progress.borrow().clone();
+        audit.record(AuditEntry {
+            principal: Some("cli".to_string()),
+            outcome: if p.done { "success" } else { "incomplete" }.to_string(),
+            details: serde_json::json!({
+                "key_id": new_key_id,
+                "tenant_id": tenant_id,
+                "processed": p.processed,
+                "failed": p.failed,
+            }),
+            ..AuditEntry::new("encryption_key.rotate")
+        });
+        audit.close().await;
+        return Ok(());
+    }
+
+    // `issue-key [--tenant <id>] <scope>...` creates an API key and prints it; the secret is not stored
+    if args.get(1).map(String::as_str) == Some("issue-key") {
+        let (tenant_id, scopes) = match args.get(2).map(String::as_str) {
+            Some("--tenant") => (
+                args.get(3).expect("Usage: issue-key [--tenant <id>] <scope>...").clone(),
+                args[4..].to_vec(),
+            ),
+            _ => (DEFAULT_TENANT.to_string(), args[2..].to_vec()),
+        };
+        let issued = generate_api_key();
+        let keys = tenant_keys.for_tenant(&tenant_id);
+        key_store
+            .insert(&ApiKeyRecord::from_issued(&issued, &tenant_id, keys.as_ref(), scopes.clone(), None))
+            .await
+            .expect("Failed to store API key");
+        println!("API key: {}", issued.key);
+        println!("Signing secret: {}", base64::encode(issued.signing_secret));
+
+        audit.record(AuditEntry {
+            principal: Some("cli".to_string()),
+            outcome: "success".to_string(),
+            details: serde_json::json!({ "key_prefix": issued.prefix, "tenant_id": tenant_id, "scopes": scopes }),
+            ..AuditEntry::new("api_key.create")
+        });
+        audit.close().await;
+        return Ok(());
+    }
+
+    // `revoke-key <prefix>` revokes an API key
+    if args.get(1).map(String::as_str) == Some("revoke-key") {
+        let prefix = args.get(2).expect("Usage: revoke-key <prefix>");
+        let revoked = key_store.revoke(prefix).await.expect("Failed to revoke API key");
+        if revoked {
+            println!("Revoked API key {}", prefix);
+        } else {
+            println!("No active API key with prefix {}", prefix);
+        }
+
+        audit.record(AuditEntry {
+            principal: Some("cli".to_string()),
+            outcome: if revoked { "success" } else { "not_found" }.to_string(),
+            details: serde_json::json!({ "key_prefix": prefix }),
+            ..AuditEntry::new("api_key.revoke")
+        });
+        audit.close().await;
+        return Ok(());
+    }
+
+    // Install configured keys, so a key store without the CLI (e.g. in memory) is usable
+    for seed in &config.seed_keys {
+        let (prefix, secret) = parse_api_key(&seed.key)
+            .filter(|(prefix, _)| is_issued_key_prefix(prefix))
+            .expect("Seed keys must have the `prefix.secret` form printed by issue-key");
+        if key_store.find_by_prefix(prefix).await.expect("Failed to look up API key").is_none() {
+            key_store
+                .insert(&ApiKeyRecord {
+                    prefix: prefix.to_string(),
+                    key_hash: hash_api_key_secret(secret),
+                    scopes: seed.scopes.clone(),
+                    issued_at: Utc::now(),
+                    expires_at: None,
+                    revoked_at: None,
+                    last_used_at: None,
+                    // Seed keys authenticate with API-KEY only, never signed requests
+                    signing_key: None,
+                    rate_limits: RateLimits::default(),
+                    tenant_id: seed.tenant_id.clone(),
+                })
+                .await
+                .expect("Failed to store seed key");
+        }
+    }
+
+    // Bearer token verification keys
+    let jwt = match config.jwt.clone() {
+        Some(jwt_config) => Some(JwtValidator::load(jwt_config).await.expect("Failed to load JWKS")),
+        None => None,
+    };
+
+    // Shared authentication state
+    let auth = web::Data::new(Authenticator {
+        key_store: key_store.clone(),
+        tenant_keys: tenant_keys.clone(),
+        nonces: NonceCache::default(),
+        auth_cache: AuthCache::new(config.auth_cache_ttl, config.auth_cache_capacity),
+        rejected_keys: RejectedKeyCache::new(config.rejected_key_cache_ttl, config.rejected_key_cache_capacity),
+        lockout: FailureTracker::new(config.lockout.clone()),
+        audit,
+        last_used: LastUsedTracker::start(key_store.clone()),
+        jwt,
+        client_identities: config
+            .tls
+            .as_ref()
+            .map(|tls| tls.client_identities.clone())
+            .unwrap_or_default(),
+    });
+
+    // Rate limits, shared by every worker
+    for limits in std::iter::once(&config.default_rate_limits)
+        .chain(config.ip_rate_limits.iter())
+        .chain(config.tenants.values().map(|tenant| &tenant.rate_limits))
+    {
+        limits.validate().expect("Invalid rate limits in Config");
+    }
+    let rate_limiter = web::Data::new(RateLimiter::new(
+        config.default_rate_limits.clone(),
+        config
+            .tenants
+            .iter()
+            .map(|(tenant_id, tenant)| (tenant_id.clone(), tenant.rate_limits.clone()))
+            .collect(),
+        config.ip_rate_limits.clone(),
+    ));
+
+    let swept = rate_limiter.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RATE_LIMIT_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            swept.sweep();
+        }
+    });
+
+    // Notification channels
+    let mut channels = ChannelRegistry::from_config(&config);
+    match &db_pool {
+        Some(db_pool) => channels.register(Arc::new(WebhookChannel::new(
+            db_pool.clone(),
+            tenant_keys.clone(),
+            config.webhooks.clone(),
+        ))),
+        // Webhook endpoints are registered in Postgres
+        None => channels.disable("webhook", "storage_disabled"),
+    }
+    if let Some(email) = &config.email {
+        channels.register(Arc::new(EmailChannel::new(email.clone())));
+    }
+    for chat in &config.chat_channels {
+        channels.register(Arc::new(ChatChannel::new(chat.clone())));
+    }
+    if let Some(sms) = &config.sms {
+        channels.register(Arc::new(SmsChannel::new(sms.clone())));
+    }
+    let channels = web::Data::new(channels);
+
+    // TLS termination, read before `config` moves into the server factory
+    let tls_config = config.tls.as_ref().map(tls_server_config).transpose()?;
+    let bind_address = config.bind_address.clone();
+
+    // Follow the token issuer's key rotations
+    if auth.jwt.is_some() {
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(JWKS_REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Some(jwt) = &auth.jwt {
+                    if let Err(e) = jwt.refresh().await {
+                        eprintln!("Failed to refresh JWKS: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    // Forget expired nonces, whatever the request rate
+    let swept = auth.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(NONCE_CACHE_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            swept.nonces.sweep();
+        }
+    });
+
+    // Resume any re-encryption interrupted by a restart
+    if let Some(db_pool) = &db_pool {
+        for (keys, tenants) in tenant_keys.providers() {
+            start_reencryption(keys, tenants, db_pool.clone());
+        }
+    }
+
+    // Pick up rotations made by `rotate-key` without a restart
+    let reloaded_keys = tenant_keys.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(KEY_RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = reloaded_keys.reload() {
+                eprintln!("Failed to reload key files: {}", e);
+            }
+        }
+    });
+
+    let server = HttpServer::new(move || {
+        App::new()
+            // Tag every request with an id, echoed back and recorded in the audit log
+            .wrap_fn(|req, srv| {
+                let request_id = assign_request_id(&req);
+                let response = srv.call(req);
+                async move {
+                    let mut response = response.await?;
+                    if let Ok(value) = HeaderValue::from_str(&request_id) {
+                        response.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    Ok(response)
+                }
+            })
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(tenant_keys.clone()))
+            .app_data(web::Data::new(db_pool.clone()))
+            .app_data(web::Data::new(event_store.clone()))
+            .app_data(auth.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(channels.clone())
+            .app_data(field_policies.clone())
+            .app_data(signer.clone())
+            .service(web::resource("/api/notify").route(web::post().to(api_gateway)))
+            .service(web::resource("/api/notify/{event_id}").route(web::get().to(notification_status)))
+            .service(web::resource("/health").route(web::get().to(health_check)))
+            .service(web::resource("/health/channels").route(web::get().to(channel_health)))
+            .service(
+                web::resource("/admin/keys")
+                    .route(web::post().to(admin_create_key))
+                    .route(web::get().to(admin_list_keys)),
+            )
+            .service(
+                web::resource("/admin/keys/{prefix}")
+                    .route(web::get().to(admin_describe_key))
+                    .route(web::delete().to(admin_revoke_key)),
+            )
+            .service(web::resource("/admin/keys/{prefix}/rotate").route(web::post().to(admin_rotate_key)))
+            .service(web::resource("/.well-known/jwks.json").route(web::get().to(jwks_endpoint)))
+            .service(
+                web::resource("/api/recipients/{recipient_id}/keys")
+                    .route(web::post().to(upload_recipient_key))
+                    .route(web::get().to(get_recipient_keys)),
+            )
+            .service(
+                web::resource("/api/recipients/{recipient_id}/keys/{key_id}")
+                    .route(web::delete().to(delete_recipient_key)),
+            )
+            .service(
+                web::resource("/api/webhooks")
+                    .route(web::post().to(register_webhook))
+                    .route(web::get().to(get_webhooks)),
+            )
+            .service(web::resource("/api/webhooks/{webhook_id}").route(web::delete().to(delete_webhook)))
+    })
+    .on_connect(client_certificate);
+
+    match tls_config {
+        Some(tls_config) => server.bind_rustls(bind_address, tls_config)?.run().await,
+        None => server.bind(bind_address)?.run().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Read one HTTP/1.1 request: lowercased header names -> values, and the body
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> (HashMap<String, String>, Vec<u8>) {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let header_end = loop {
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed mid-request");
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+                break end + 4;
+            }
+        };
+
+        let head = String::from_utf8(buf[..header_end].to_vec()).unwrap();
+        let headers: HashMap<String, String> = head
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        let length: usize = headers.get("content-length").map_or(0, |length| length.parse().unwrap());
+
+        let mut body = buf[header_end..].to_vec();
+        while body.len() < length {
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed mid-body");
+            body.extend_from_slice(&chunk[..n]);
+        }
+        (headers, body)
+    }
+
+    fn test_tenant_keys() -> Arc<TenantKeys> {
+        Arc::new(TenantKeys {
+            shared: Arc::new(LocalKeyProvider {
+                path: PathBuf::from("unused-test-keys.json"),
+                inner: RwLock::new(InMemoryKeyProvider::generate("test-kek")),
+            }),
+            tenants: HashMap::new(),
+        })
+    }
+
+    fn test_webhook_config() -> WebhookConfig {
+        WebhookConfig {
+            max_attempts: 3,
+            retry_backoff: Duration::from_millis(10),
+            default_timeout: Duration::from_secs(5),
+            max_timeout: Duration::from_secs(5),
+            delivery_deadline: Duration::from_secs(10),
+            allow_private_targets: true,
+        }
+    }
+
+    #[test]
+    fn webhook_targets_exclude_special_purpose_ranges() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "169.254.169.254",
+            "100.64.0.1",
+            "192.0.0.8",
+            "198.18.0.1",
+            "198.19.255.255",
+            "240.0.0.1",
+            "255.255.255.255",
+            "::1",
+            "fd00::1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:a9fe:a9fe::1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{} should be refused", ip);
+        }
+        for ip in ["8.8.8.8", "198.20.0.1", "2606:4700::1111"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{} should be accepted", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn webhook_targets_need_https_and_public_addresses_unless_allowed() {
+        assert!(resolve_webhook_target("http://127.0.0.1:8080/hook", false).await.is_err());
+        assert!(resolve_webhook_target("https://127.0.0.1:8080/hook", false).await.is_err());
+
+        let (url, addrs) = resolve_webhook_target("http://127.0.0.1:8080/hook", true).await.unwrap();
+        assert_eq!(url.scheme(), "http");
+        assert_eq!(addrs, vec!["127.0.0.1:8080".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn webhook_delivery_is_signed_and_retried() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Fail the first attempt so the delivery is retried, then accept
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in ["503 Service Unavailable", "200 OK"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                requests.push(read_request(&mut stream).await);
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let keys = test_tenant_keys();
+        let tenant_keys = keys.for_tenant(DEFAULT_TENANT);
+        let secret = [7u8; 32];
+        let endpoint = WebhookEndpoint {
+            id: Uuid::new_v4(),
+            tenant_id: DEFAULT_TENANT.to_string(),
+            url: format!("http://{}/hook", addr),
+            event_types: Vec::new(),
+            timeout: Duration::from_secs(5),
+            secret: tenant_keys.wrap_key(&secret).unwrap(),
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+
+        let event = Event {
+            id: Uuid::new_v4(),
+            event_type: "user.created".to_string(),
+            recipient: None,
+            data: serde_json::json!({ "message": "hello" }),
+        };
+        let encrypted = EncryptedEvent {
+            id: event.id,
+            tenant_id: DEFAULT_TENANT.to_string(),
+            event_type: event.event_type.clone(),
+            recipient: None,
+            algorithm: "AES-256-GCM".to_string(),
+            wrapped_key: tenant_keys.wrap_key(&[1u8; 32]).unwrap(),
+            data: event.data.clone(),
+            encrypted_fields: Vec::new(),
+        };
+        let signer = NotificationSigner {
+            active_key_id: "test-signing".to_string(),
+            keys: HashMap::from([("test-signing".to_string(), SigningKey::from_bytes(&[3u8; 32]))]),
+        };
+        let signed = signer.sign(serde_json::to_vec(&encrypted).unwrap());
+        let delivery = Delivery {
+            tenant_id: DEFAULT_TENANT,
+            event: &event,
+            encrypted: &encrypted,
+            signed: &signed,
+            credentials: None,
+        };
+
+        let pool = PgPoolOptions::new().connect_lazy("postgres://localhost/unused").unwrap();
+        let channel = WebhookChannel::new(pool, keys.clone(), test_webhook_config());
+        channel.deliver(&endpoint, &delivery).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        for (attempt, (headers, body)) in requests.iter().enumerate() {
+            assert_eq!(body, &signed.payload);
+            assert_eq!(headers["x-webhook-event-id"], event.id.to_string());
+            assert_eq!(headers["x-webhook-attempt"], (attempt + 1).to_string());
+
+            let timestamp = &headers["x-webhook-timestamp"];
+            let sent_at: i64 = timestamp.parse().unwrap();
+            assert!((Utc::now().timestamp() - sent_at).abs() < 60);
+
+            let mut mac = HmacSha256::new_from_slice(&secret).unwrap();
+            mac.update(timestamp.as_bytes());
+            mac.update(b".");
+            mac.update(body);
+            let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+            assert_eq!(headers["x-webhook-signature"], expected);
+        }
+
+        // Both attempts went through one cached client
+        assert_eq!(channel.clients.lock().unwrap().len(), 1);
+    }
+
+    async fn issue_test_key(store: &InMemoryKeyStore, keys: &dyn KeyProvider, scopes: &[&str]) -> IssuedApiKey {
+        let issued = generate_api_key();
+        let scopes = scopes.iter().map(|scope| scope.to_string()).collect();
+        store
+            .insert(&ApiKeyRecord::from_issued(&issued, "acme", keys, scopes, None))
+            .await
+            .unwrap();
+        issued
+    }
+
+    #[tokio::test]
+    async fn issued_keys_verify_against_the_key_store() {
+        let store = InMemoryKeyStore::default();
+        let keys = InMemoryKeyProvider::generate("kek-1");
+        let issued = issue_test_key(&store, &keys, &[SCOPE_NOTIFY_SEND]).await;
+        let cache = AuthCache::new(Duration::from_secs(30), 10);
+        let rejected = RejectedKeyCache::new(Duration::from_secs(5), 10);
+
+        let principal = api_key_authenticator(&store, &cache, &rejected, issued.key.clone()).await.unwrap();
+        assert_eq!(principal.subject, issued.prefix);
+        assert_eq!(principal.tenant, "acme");
+
+        // Only the hash of the secret is stored
+        let record = store.find_by_prefix(&issued.prefix).await.unwrap().unwrap();
+        assert!(!record.key_hash.is_empty());
+        assert_ne!(record.key_hash, issued.key.as_bytes());
+
+        let wrong_secret = format!("{}.{}", issued.prefix, "not-the-secret");
+        for api_key in [wrong_secret.as_str(), "0123456789abcdef.secret", "no-prefix-at-all"] {
+            let result = api_key_authenticator(&store, &cache, &rejected, api_key.to_string()).await;
+            assert_eq!(result.err(), Some(AuthError::InvalidKey), "{}", api_key);
+            assert!(rejected.contains(&AuthCache::digest(api_key)));
+        }
+
+        // Prefixes are unique
+        let record = ApiKeyRecord::from_issued(&issued, "acme", &keys, Vec::new(), None);
+        assert!(store.insert(&record).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn revoked_and_expired_keys_are_rejected() {
+        let store = InMemoryKeyStore::default();
+        let keys = InMemoryKeyProvider::generate("kek-1");
+        let cache = AuthCache::new(Duration::from_secs(30), 10);
+        let rejected = RejectedKeyCache::new(Duration::from_secs(5), 10);
+
+        let revoked = issue_test_key(&store, &keys, &[SCOPE_NOTIFY_SEND]).await;
+        assert!(api_key_authenticator(&store, &cache, &rejected, revoked.key.clone()).await.is_ok());
+        assert!(store.revoke(&revoked.prefix).await.unwrap());
+        assert!(!store.revoke(&revoked.prefix).await.unwrap());
+        // Cached records keep their old lifecycle until invalidated, as the admin endpoints do
+        cache.invalidate(&revoked.prefix);
+        let result = api_key_authenticator(&store, &cache, &rejected, revoked.key.clone()).await;
+        assert_eq!(result.err(), Some(AuthError::RevokedKey));
+
+        let expired = issue_test_key(&store, &keys, &[SCOPE_NOTIFY_SEND]).await;
+        let past = Utc::now() - chrono::Duration::seconds(1);
+        assert!(store.set_expiry(&expired.prefix, Some(past)).await.unwrap());
+        let result = api_key_authenticator(&store, &cache, &rejected, expired.key.clone()).await;
+        assert_eq!(result.err(), Some(AuthError::ExpiredKey));
+
+        let future = Utc::now() + chrono::Duration::hours(1);
+        assert!(store.set_expiry(&expired.prefix, Some(future)).await.unwrap());
+        cache.invalidate(&expired.prefix);
+        assert!(api_key_authenticator(&store, &cache, &rejected, expired.key.clone()).await.is_ok());
+        assert!(!store.set_expiry("unknown", None).await.unwrap());
+    }
+
+    #[test]
+    fn scopes_cover_narrower_scopes() {
+        let principal = Principal {
+            subject: "test".to_string(),
+            tenant: "acme".to_string(),
+            scopes: vec![SCOPE_NOTIFY_SEND.to_string(), "recipients:*".to_string()],
+            rate_limits: RateLimits::default(),
+        };
+
+        assert!(principal.has_scope("notify:send"));
+        assert!(principal.has_scope("notify:send:payment.completed"));
+        assert!(!principal.has_scope("notify:sender"));
+        assert!(!principal.has_scope("notify"));
+        assert!(principal.has_scope(SCOPE_RECIPIENT_KEYS));
+        assert!(!principal.has_scope(SCOPE_KEYS_ADMIN));
+
+        let response = require_scope(&principal, SCOPE_KEYS_ADMIN).unwrap_err();
+        assert_eq!(response.status(), actix_web::http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn events_round_trip_through_encryption() {
+        let keys = InMemoryKeyProvider::generate("kek-1");
+        let data = serde_json::json!({
+            "user": { "email": "ada@example.com", "name": "Ada" },
+            "items": [{ "sku": "a-1" }, { "sku": "b-2" }],
+            "message": "hello",
+        });
+        let event = Event {
+            id: Uuid::new_v4(),
+            event_type: "order.created".to_string(),
+            recipient: None,
+            data: data.clone(),
+        };
+        let policy = vec![JsonPath::parse("$.user.email").unwrap(), JsonPath::parse("$.items[*].sku").unwrap()];
+
+        let encrypted = encrypt_event(event.clone(), "acme", &keys, &policy, None).await.unwrap();
+        assert_eq!(encrypted.wrapped_key.kek_id, "kek-1");
+        assert_eq!(encrypted.encrypted_fields.len(), 3);
+        assert_eq!(encrypted.data["user"]["email"], Value::Null);
+        assert_eq!(encrypted.data["items"][1]["sku"], Value::Null);
+        assert_eq!(encrypted.data["message"], "hello");
+
+        let decrypted = decrypt_event(&encrypted, &keys).await.unwrap();
+        assert_eq!(decrypted.data, data);
+
+        // Older keys stay usable for decryption after a rotation
+        keys.rotate_key(EncryptionKey::generate("kek-2")).unwrap();
+        assert_eq!(keys.key_ids(), vec!["kek-1".to_string(), "kek-2".to_string()]);
+        assert_eq!(decrypt_event(&encrypted, &keys).await.unwrap().data, data);
+        assert!(keys.rotate_key(EncryptionKey::generate("kek-2")).is_err());
+
+        // Another provider can't unwrap the data key
+        let other = InMemoryKeyProvider::generate("kek-1");
+        assert!(decrypt_event(&encrypted, &other).await.is_err());
+
+        // Fields are bound to their event and path
+        let mut moved = encrypted;
+        moved.id = Uuid::new_v4();
+        assert!(decrypt_event(&moved, &keys).await.is_err());
+    }
+
+    #[test]
+    fn failures_ban_ips_but_only_slow_down_prefixes() {
+        let tracker = FailureTracker::new(LockoutPolicy {
+            window: Duration::from_secs(60),
+            delay_after: 2,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(400),
+            ban_after: 5,
+            ban_duration: Duration::from_secs(60),
+        });
+        let attacker: IpAddr = "203.0.113.1".parse().unwrap();
+        let owner: IpAddr = "203.0.113.2".parse().unwrap();
+
+        let delays: Vec<Duration> = (0..5).map(|_| tracker.record_failure(Some(attacker), Some("abc"))).collect();
+        assert_eq!(delays[..2], [Duration::ZERO, Duration::ZERO]);
+        assert_eq!(delays[2..], [Duration::from_millis(100), Duration::from_millis(200), Duration::from_millis(400)]);
+        assert!(matches!(tracker.check(Some(attacker)), Err(AuthError::LockedOut { .. })));
+
+        // The prefix is not banned, so its owner gets through from elsewhere
+        assert_eq!(tracker.check(Some(owner)), Ok(()));
+
+        // Failures against the prefix from other IPs are still slowed down
+        let delay = tracker.record_failure(Some("198.51.100.7".parse().unwrap()), Some("abc"));
+        assert_eq!(delay, Duration::from_millis(400));
+        assert_eq!(tracker.check(Some("198.51.100.7".parse().unwrap())), Ok(()));
+    }
+
+    #[test]
+    fn rejected_keys_are_bounded_and_expire() {
+        let rejected = RejectedKeyCache::new(Duration::from_millis(50), 2);
+        let digests: Vec<[u8; 32]> = ["a", "b", "c"].iter().map(|key| AuthCache::digest(key)).collect();
+
+        for digest in &digests {
+            rejected.insert(*digest);
+        }
+        // The oldest entry made room for the newest
+        assert!(!rejected.contains(&digests[0]));
+        assert!(rejected.contains(&digests[1]));
+        assert!(rejected.contains(&digests[2]));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!rejected.contains(&digests[2]));
+    }
+
+    #[test]
+    fn json_paths_round_trip_through_display() {
+        for key in ["email", "first name", "it's", "a]b", "['x']", "back\\slash", "*", "", "1"] {
+            let path = JsonPath(vec![
+                PathSegment::Key("user".to_string()),
+                PathSegment::Key(key.to_string()),
+                PathSegment::Index(3),
+                PathSegment::Wildcard,
+            ]);
+            let rendered = path.to_string();
+            assert_eq!(JsonPath::parse(&rendered).unwrap(), path, "{}", rendered);
+        }
+
+        assert_eq!(
+            JsonPath::parse("$.items[*]['sku \\'a\\']'][0]").unwrap().0,
+            vec![
+                PathSegment::Key("items".to_string()),
+                PathSegment::Wildcard,
+                PathSegment::Key("sku 'a']".to_string()),
+                PathSegment::Index(0),
+            ]
+        );
+        for invalid in ["user", "$.", "$['open", "$['x'", "$['x\\']", "$[x]", "$..a"] {
+            assert!(JsonPath::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn templates_only_see_fields_left_in_clear() {
+        let policies = FieldEncryptionPolicies::from_config(
+            &HashMap::from([("system.alert".to_string(), Vec::new()), ("payment.completed".to_string(), vec!["$.card".to_string()])]),
+            &["$".to_string()],
+        )
+        .unwrap();
+        let event = |event_type: &str| Event {
+            id: Uuid::nil(),
+            event_type: event_type.to_string(),
+            recipient: None,
+            data: serde_json::json!({ "message": "disk full", "card": "4242" }),
+        };
+        let render = |event_type: &str| {
+            let redacted = redact_event(&event(event_type), policies.paths_for(event_type));
+            render_template("{{event_type}}: {{data.message}} {{data.card}}", &redacted, None)
+        };
+
+        assert_eq!(render("system.alert"), "system.alert: disk full 4242");
+        assert_eq!(render("payment.completed"), "payment.completed: disk full ");
+        // The default policy encrypts the whole payload
+        assert_eq!(render("user.created"), "user.created:  ");
+    }
+
+    #[test]
+    fn e164_numbers() {
+        for number in ["+15005550006", "+442071838750", "+12", "+123456789012345"] {
+            assert!(is_e164(number), "{}", number);
+        }
+        for number in ["15005550006", "+", "+1", "+0123456", "+1234567890123456", "+1 500 555", "+1-500", "++15005550006", ""] {
+            assert!(!is_e164(number), "{}", number);
+        }
+    }
+
+    #[test]
+    fn sms_messages_split_into_numbered_segments() {
+        // One message fits 160 GSM-7 septets; extension characters take two
+        assert_eq!(sms_segments(&"a".repeat(160), 3), vec!["a".repeat(160)]);
+        assert_eq!(sms_segments(&"€".repeat(80), 3), vec!["€".repeat(80)]);
+        assert_eq!(sms_segments(&"€".repeat(81), 3).len(), 2);
+
+        // Longer messages are numbered, leaving room for the prefix
+        let segments = sms_segments(&"a".repeat(200), 3);
+        assert_eq!(segments, vec![format!("(1/2) {}", "a".repeat(154)), format!("(2/2) {}", "a".repeat(46))]);
+
+        // Anything outside GSM-7 allows 70 UTF-16 code units
+        assert_eq!(sms_segments(&"ж".repeat(70), 3), vec!["ж".repeat(70)]);
+        let segments = sms_segments(&"ж".repeat(100), 3);
+        assert_eq!(segments, vec![format!("(1/2) {}", "ж".repeat(64)), format!("(2/2) {}", "ж".repeat(36))]);
+        let segments = sms_segments(&"😀".repeat(40), 3);
+        assert_eq!(segments, vec![format!("(1/2) {}", "😀".repeat(32)), format!("(2/2) {}", "😀".repeat(8))]);
+
+        // Past max_segments the message is cut and marked
+        let segments = sms_segments(&"a".repeat(1000), 2);
+        assert_eq!(segments, vec![format!("(1/2) {}", "a".repeat(154)), format!("(2/2) {}...", "a".repeat(151))]);
+    }
+
+    #[test]
+    fn sms_provider_responses() {
+        let created = reqwest::StatusCode::CREATED;
+        let ok = reqwest::StatusCode::OK;
+        let by_sid = SmsSuccess {
+            statuses: vec![201],
+            json_path: Some("sid".to_string()),
+            expected: None,
+        };
+        assert!(sms_accepted(&by_sid, created, Some(&serde_json::json!({ "sid": "SM1" }))));
+        assert!(!sms_accepted(&by_sid, ok, Some(&serde_json::json!({ "sid": "SM1" }))));
+        assert!(!sms_accepted(&by_sid, created, Some(&serde_json::json!({ "sid": null }))));
+        assert!(!sms_accepted(&by_sid, created, Some(&serde_json::json!({}))));
+        assert!(!sms_accepted(&by_sid, created, None));
+
+        let by_value = SmsSuccess {
+            statuses: Vec::new(),
+            json_path: Some("messages.0.status".to_string()),
+            expected: Some("0".to_string()),
+        };
+        assert!(sms_accepted(&by_value, ok, Some(&serde_json::json!({ "messages": [{ "status": "0" }] }))));
+        assert!(sms_accepted(&by_value, ok, Some(&serde_json::json!({ "messages": [{ "status": 0 }] }))));
+        assert!(!sms_accepted(&by_value, ok, Some(&serde_json::json!({ "messages": [{ "status": "4" }] }))));
+        assert!(!sms_accepted(&by_value, reqwest::StatusCode::BAD_GATEWAY, Some(&serde_json::json!({ "messages": [{ "status": "0" }] }))));
+
+        let any_2xx = SmsSuccess {
+            statuses: Vec::new(),
+            json_path: None,
+            expected: None,
+        };
+        assert!(sms_accepted(&any_2xx, reqwest::StatusCode::ACCEPTED, None));
+        assert!(!sms_accepted(&any_2xx, reqwest::StatusCode::FOUND, None));
+    }
+
+    #[test]
+    fn sms_provider_urls_encode_substituted_values() {
+        let values = HashMap::from([("account_sid".to_string(), "AC1/../Other?x=1#".to_string())]);
+        let url = fill_placeholders(
+            "https://api.example.com/Accounts/{{account_sid}}/Messages.json",
+            &values,
+            Some(percent_encode_component),
+        )
+        .unwrap();
+        assert_eq!(url, "https://api.example.com/Accounts/AC1%2F..%2FOther%3Fx%3D1%23/Messages.json");
+        assert_eq!(percent_encode_component("ü ~"), "%C3%BC%20~");
+        assert!(fill_placeholders("{{missing}}", &values, None).is_err());
+    }
+
+    #[test]
+    fn chat_payloads_escape_event_data() {
+        let event = Event {
+            id: Uuid::nil(),
+            event_type: "deploy".to_string(),
+            recipient: None,
+            data: serde_json::json!({ "name": "<!channel> *now*", "link": "[x](https://evil.example)" }),
+        };
+        let formatting = ChatFormatting {
+            title: "{{data.name}}".to_string(),
+            text: "{{data.link}}".to_string(),
+            fields: Vec::new(),
+            color: None,
+        };
+
+        let slack = chat_payload(ChatFormat::Slack, &formatting, &event);
+        assert_eq!(slack["text"], "&lt;!channel&gt; *now*");
+        // The header block is plain text and shown as is
+        assert_eq!(slack["blocks"][0]["text"]["text"], "<!channel> *now*");
+
+        let card = chat_payload(ChatFormat::TeamsMessageCard, &formatting, &event);
+        assert_eq!(card["text"], "\\[x\\](https://evil.example)");
+
+        let card = chat_payload(ChatFormat::TeamsAdaptiveCard, &formatting, &event);
+        let body = &card["attachments"][0]["content"]["body"];
+        assert_eq!(body[0]["text"], "&lt;!channel&gt; \\*now\\*");
+        assert_eq!(body[1]["text"], "\\[x\\](https://evil.example)");
+    }
+
+    #[test]
+    fn token_scopes_accept_strings_and_arrays() {
+        let spaced: TokenClaims = serde_json::from_value(serde_json::json!({
+            "sub": "client",
+            "scp": "events:write audit:read",
+        }))
+        .unwrap();
+        assert_eq!(
+            spaced.scp.map(TokenScopes::into_vec).unwrap_or_default(),
+            vec!["events:write", "audit:read"]
+        );
+
+        let list: TokenClaims = serde_json::from_value(serde_json::json!({
+            "sub": "client",
+            "scp": ["events:write", "audit:read"],
+        }))
+        .unwrap();
+        assert_eq!(
+            list.scp.map(TokenScopes::into_vec).unwrap_or_default(),
+            vec!["events:write", "audit:read"]
+        );
+
+        let none: TokenClaims = serde_json::from_value(serde_json::json!({ "sub": "client" })).unwrap();
+        assert!(none.scp.is_none());
+    }
+
+    // Minimal SMTP server accepting every message; each one is sent on `messages`
+    async fn smtp_sink(stream: tokio::net::TcpStream, messages: mpsc::UnboundedSender<String>) {
+        use tokio::io::AsyncBufReadExt;
+
+        let (read, mut write) = stream.into_split();
+        let mut lines = tokio::io::BufReader::new(read).lines();
+        write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = line.to_ascii_uppercase();
+            if command.starts_with("DATA") {
+                write.write_all(b"354 go ahead\r\n").await.unwrap();
+                let mut message = String::new();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line == "." {
+                        break;
+                    }
+                    message.push_str(&line);
+                    message.push('\n');
+                }
+                messages.send(message).unwrap();
+                write.write_all(b"250 queued\r\n").await.unwrap();
+            } else if command.starts_with("QUIT") {
+                write.write_all(b"221 bye\r\n").await.unwrap();
+                break;
+            } else {
+                write.write_all(b"250 sink\r\n").await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn email_transports_are_reused_per_login() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (messages_tx, mut messages) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(smtp_sink(stream, messages_tx.clone()));
+            }
+        });
+
+        let channel = EmailChannel::new(EmailConfig {
+            relay: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "notifier@example.com".to_string(),
+            to: vec!["ops@example.com".to_string()],
+            timeout: Duration::from_secs(5),
+            templates: HashMap::new(),
+            default_template: EmailTemplate {
+                subject: "Notification: {{event_type}}".to_string(),
+                text: "Event {{id}}".to_string(),
+                html: None,
+            },
+        });
+
+        let keys = test_tenant_keys();
+        let event = Event {
+            id: Uuid::new_v4(),
+            event_type: "user.created".to_string(),
+            recipient: None,
+            data: serde_json::json!({}),
+        };
+        let encrypted = EncryptedEvent {
+            id: event.id,
+            tenant_id: DEFAULT_TENANT.to_string(),
+            event_type: event.event_type.clone(),
+            recipient: None,
+            algorithm: "AES-256-GCM".to_string(),
+            wrapped_key: keys.for_tenant(DEFAULT_TENANT).wrap_key(&[1u8; 32]).unwrap(),
+            data: event.data.clone(),
+            encrypted_fields: Vec::new(),
+        };
+        let signer = NotificationSigner {
+            active_key_id: "test-signing".to_string(),
+            keys: HashMap::from([("test-signing".to_string(), SigningKey::from_bytes(&[3u8; 32]))]),
+        };
+        let signed = signer.sign(serde_json::to_vec(&encrypted).unwrap());
+        let delivery = Delivery {
+            tenant_id: DEFAULT_TENANT,
+            event: &event,
+            encrypted: &encrypted,
+            signed: &signed,
+            credentials: None,
+        };
+
+        for _ in 0..2 {
+            assert_eq!(channel.send(&delivery).await.unwrap(), "queued");
+            let message = messages.recv().await.unwrap();
+            assert!(message.contains("Subject: Notification: user.created"));
+            assert!(message.contains(&format!("Event {}", event.id)));
+        }
+        // Both sends went through one cached transport
+        assert_eq!(channel.transports.lock().unwrap().len(), 1);
+
+        // A tenant's own login gets a transport of its own, and reuses it
+        let credentials = HashMap::from([
+            ("username".to_string(), "tenant".to_string()),
+            ("password".to_string(), "secret".to_string()),
+        ]);
+        channel.transport(Some(&credentials)).unwrap();
+        channel.transport(Some(&credentials)).unwrap();
+        assert_eq!(channel.transports.lock().unwrap().len(), 2);
+    }
 }
\ No newline at end of file